@@ -4,4 +4,4 @@ pub enum ArithmeticOperation {
     Sub,
     Mul,
     Div,
-}
\ No newline at end of file
+}
@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use crate::instruction::{Instruction, Opcode};
+use crate::operand::Operand;
+use crate::{INSTRUCTION_SIZE, MEMORY_SIZE};
+
+#[derive(Debug, Clone)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Assembly {
+    pub image: Vec<u8>,
+    pub labels: BTreeMap<String, u16>,
+    // Source line (starting at 1) of the instruction at every address
+    pub source_lines: BTreeMap<u16, usize>,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Number(u16),
+    Label(String),
+}
+
+#[derive(Debug, Clone)]
+enum OperandSyntax {
+    Register(Operand),
+    Constant(Value),
+    Memory(Value),
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    Instruction(Opcode, Vec<OperandSyntax>),
+    Bytes(Vec<Value>),
+    Words(Vec<Value>),
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    number: usize,
+    item: Item,
+}
+
+// Assembles source text into a program image that can be passed to `Rsmisc::new`
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    Ok(assemble_with_symbols(source)?.image)
+}
+
+// Same as `assemble`, but also returns the address of every label and the source line of every
+// instruction
+pub fn assemble_with_symbols(source: &str) -> Result<Assembly, AsmError> {
+    let mut lines = Vec::new();
+    let mut labels = BTreeMap::new();
+    let mut address: usize = 0;
+
+    // First pass: parse every line and assign addresses to labels
+    for (index, text) in source.lines().enumerate() {
+        let number = index + 1;
+        let mut text = strip_comment(text).trim();
+
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+
+            if !is_label(label) {
+                return Err(error(number, format!("invalid label '{}'", label)));
+            }
+            if address > 0xffff {
+                return Err(error(number, format!("label '{}' is out of range", label)));
+            }
+            if labels.insert(label.to_string(), address as u16).is_some() {
+                return Err(error(number, format!("duplicate label '{}'", label)));
+            }
+
+            text = text[colon + 1..].trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let item = parse_item(number, text)?;
+        address += item_size(&item);
+        lines.push(Line { number, item });
+    }
+
+    if address > MEMORY_SIZE {
+        return Err(error(
+            lines.last().map_or(0, |line| line.number),
+            format!("program is too large (0x{:x} bytes)", address),
+        ));
+    }
+
+    // Second pass: resolve labels and emit the image
+    let mut image = Vec::with_capacity(address);
+    let mut source_lines = BTreeMap::new();
+
+    for line in &lines {
+        match &line.item {
+            Item::Instruction(op_code, operands) => {
+                let instruction = build_instruction(line.number, *op_code, operands, &labels)?;
+                source_lines.insert(image.len() as u16, line.number);
+                image.extend_from_slice(&instruction.to_bytes());
+            }
+            Item::Bytes(values) => {
+                for value in values {
+                    let byte = resolve(line.number, value, &labels)?;
+
+                    if byte > 0xff {
+                        return Err(error(line.number, format!("byte out of range: {:X}", byte)));
+                    }
+
+                    image.push(byte as u8);
+                }
+            }
+            Item::Words(values) => {
+                for value in values {
+                    let word = resolve(line.number, value, &labels)?;
+                    image.push((word >> 8) as u8);
+                    image.push((word & 0xff) as u8);
+                }
+            }
+        }
+    }
+
+    Ok(Assembly {
+        image,
+        labels,
+        source_lines,
+    })
+}
+
+fn error(line: usize, message: String) -> AsmError {
+    AsmError { line, message }
+}
+
+fn strip_comment(text: &str) -> &str {
+    match text.find(';') {
+        Some(index) => &text[..index],
+        None => text,
+    }
+}
+
+fn item_size(item: &Item) -> usize {
+    match item {
+        Item::Instruction(_, _) => INSTRUCTION_SIZE,
+        Item::Bytes(values) => values.len(),
+        Item::Words(values) => values.len() * 2,
+    }
+}
+
+fn parse_item(line: usize, text: &str) -> Result<Item, AsmError> {
+    let mut tokens = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty());
+    let mnemonic = tokens.next().unwrap_or_default();
+    let arguments: Vec<&str> = tokens.collect();
+
+    match mnemonic.to_ascii_lowercase().as_str() {
+        ".byte" => Ok(Item::Bytes(parse_values(line, &arguments)?)),
+        ".word" => Ok(Item::Words(parse_values(line, &arguments)?)),
+        _ => {
+            let op_code = Opcode::from_mnemonic(mnemonic)
+                .ok_or_else(|| error(line, format!("unknown mnemonic '{}'", mnemonic)))?;
+
+            if arguments.len() != op_code.operand_count() {
+                return Err(error(
+                    line,
+                    format!(
+                        "{} expects {} operand(s), found {}",
+                        mnemonic.to_ascii_uppercase(),
+                        op_code.operand_count(),
+                        arguments.len()
+                    ),
+                ));
+            }
+
+            let operands = arguments
+                .iter()
+                .map(|argument| parse_operand(line, argument))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(Item::Instruction(op_code, operands))
+        }
+    }
+}
+
+fn parse_values(line: usize, arguments: &[&str]) -> Result<Vec<Value>, AsmError> {
+    if arguments.is_empty() {
+        return Err(error(
+            line,
+            "directive expects at least one value".to_string(),
+        ));
+    }
+
+    arguments
+        .iter()
+        .map(|argument| parse_value(line, argument))
+        .collect()
+}
+
+fn parse_operand(line: usize, text: &str) -> Result<OperandSyntax, AsmError> {
+    if let Some(register) = parse_register(text) {
+        return Ok(OperandSyntax::Register(register));
+    }
+
+    match text.strip_prefix('#') {
+        Some(constant) => Ok(OperandSyntax::Constant(parse_value(line, constant)?)),
+        None => Ok(OperandSyntax::Memory(parse_value(line, text)?)),
+    }
+}
+
+// The registers an instruction or the debugger may name, in any case
+pub fn parse_register(text: &str) -> Option<Operand> {
+    match text.to_ascii_uppercase().as_str() {
+        "R1" => Some(Operand::R1),
+        "R2" => Some(Operand::R2),
+        "R3" => Some(Operand::R3),
+        "R4" => Some(Operand::R4),
+        "IP" => Some(Operand::IP),
+        _ => None,
+    }
+}
+
+// Numbers are hexadecimal, the same as `Operand::display` prints them
+fn parse_value(line: usize, text: &str) -> Result<Value, AsmError> {
+    if let Some(number) = parse_number(text) {
+        return number
+            .map(Value::Number)
+            .ok_or_else(|| error(line, format!("value out of range: {}", text)));
+    }
+
+    if is_label(text) {
+        Ok(Value::Label(text.to_string()))
+    } else {
+        Err(error(line, format!("invalid operand '{}'", text)))
+    }
+}
+
+// Returns `None` if the text is not a number and `Some(None)` if it does not fit in 16 bits
+fn parse_number(text: &str) -> Option<Option<u16>> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some(u16::from_str_radix(digits, 16).ok())
+}
+
+// Labels must not be mistaken for a register or a hexadecimal number
+fn is_label(text: &str) -> bool {
+    let mut chars = text.chars();
+    let valid = match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' || first == '.' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        }
+        _ => false,
+    };
+
+    valid && parse_register(text).is_none() && parse_number(text).is_none()
+}
+
+fn resolve(line: usize, value: &Value, labels: &BTreeMap<String, u16>) -> Result<u16, AsmError> {
+    match value {
+        Value::Number(number) => Ok(*number),
+        Value::Label(label) => labels
+            .get(label)
+            .copied()
+            .ok_or_else(|| error(line, format!("undefined label '{}'", label))),
+    }
+}
+
+fn build_instruction(
+    line: usize,
+    op_code: Opcode,
+    operands: &[OperandSyntax],
+    labels: &BTreeMap<String, u16>,
+) -> Result<Instruction, AsmError> {
+    let mut resolved = [(Operand::R1, 0), (Operand::R1, 0)];
+
+    for (index, operand) in operands.iter().enumerate() {
+        resolved[index] = match operand {
+            OperandSyntax::Register(register) => (*register, 0),
+            OperandSyntax::Constant(value) => (Operand::CT, resolve(line, value, labels)?),
+            OperandSyntax::Memory(value) => (Operand::MA, resolve(line, value, labels)?),
+        };
+    }
+
+    Ok(Instruction {
+        op_code,
+        target: resolved[0].0,
+        source: resolved[1].0,
+        target_imm: resolved[0].1,
+        source_imm: resolved[1].1,
+    })
+}
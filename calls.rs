@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use crate::trace::TraceRecord;
+
+// Names functions after the labels of an assembly, or after their address when there are none
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn new(labels: &BTreeMap<String, u16>) -> Self {
+        Symbols {
+            names: labels
+                .iter()
+                .map(|(label, address)| (*address, label.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn name(&self, address: u16) -> String {
+        self.names
+            .get(&address)
+            .cloned()
+            .unwrap_or_else(|| format!("0x{:04X}", address))
+    }
+
+    pub fn labels(&self) -> &BTreeMap<u16, String> {
+        &self.names
+    }
+}
+
+// Entries of the calls a tracer has seen made and not yet returned from, innermost last
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<u16>,
+}
+
+// How one instruction changed the call stack
+#[derive(Copy, Debug, Clone, PartialEq, Eq, Default)]
+pub struct CallChange {
+    // Entry of the function a CALL (or trap) entered
+    pub entered: Option<u16>,
+    // Entry of the function a RET returned from
+    pub returned: Option<u16>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The call stack only changes on CALL, RET and traps. Returns from calls made before tracing
+    // started have no frame and are ignored
+    pub fn follow(&mut self, record: &TraceRecord) -> CallChange {
+        let mut change = CallChange::default();
+
+        if record.call_stack.pushed.is_some() {
+            self.frames.push(record.ip_after);
+            change.entered = Some(record.ip_after);
+        }
+        if record.call_stack.popped.is_some() {
+            change.returned = self.frames.pop();
+        }
+
+        change
+    }
+
+    pub fn frames(&self) -> &[u16] {
+        &self.frames
+    }
+}
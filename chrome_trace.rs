@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::calls::{CallStack, Symbols};
+use crate::json::Json;
+use crate::lock;
+use crate::trace::{TraceRecord, Tracer};
+
+// Call timeline in the Chrome Trace Event Format, for chrome://tracing, Perfetto and Speedscope.
+// Every call becomes a duration event from the CALL (or trap) to the RET that returns from it.
+// Time is counted in executed instructions, shown by the viewers as microseconds
+#[derive(Debug, Clone, Default)]
+pub struct ChromeTrace {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    symbols: Symbols,
+    events: Vec<Json>,
+    calls: CallStack,
+    // Instruction count of the last record
+    now: u64,
+}
+
+impl ChromeTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_symbols(labels: &BTreeMap<String, u16>) -> Self {
+        let trace = Self::new();
+
+        lock(&trace.state).symbols = Symbols::new(labels);
+
+        trace
+    }
+
+    // The events recorded so far. Calls that have not returned end at the last instruction
+    pub fn to_json(&self) -> Json {
+        let state = lock(&self.state);
+        let mut events = state.events.clone();
+
+        for entry in state.calls.frames().iter().rev() {
+            let name = state.symbols.name(*entry);
+            events.push(event(&name, "E", state.now, Vec::new()));
+        }
+
+        Json::object(vec![
+            ("traceEvents", Json::from(events)),
+            ("displayTimeUnit", Json::from("ns")),
+            (
+                "otherData",
+                Json::object(vec![("timeUnit", Json::from("instructions"))]),
+            ),
+        ])
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", self.to_json())?;
+        writer.flush()
+    }
+}
+
+impl Tracer for ChromeTrace {
+    fn trace(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let mut state = lock(&self.state);
+        state.now = record.count;
+
+        let change = state.calls.follow(record);
+
+        if let Some(entry) = change.entered {
+            let args = vec![
+                ("address", Json::from(entry)),
+                ("caller", Json::from(record.ip)),
+            ];
+            let begin = event(&state.symbols.name(entry), "B", record.count, args);
+            state.events.push(begin);
+        }
+        if let Some(entry) = change.returned {
+            let end = event(&state.symbols.name(entry), "E", record.count, Vec::new());
+            state.events.push(end);
+        }
+
+        Ok(())
+    }
+}
+
+fn event(name: &str, phase: &str, timestamp: u64, args: Vec<(&str, Json)>) -> Json {
+    Json::object(vec![
+        ("name", Json::from(name)),
+        ("cat", Json::from("call")),
+        ("ph", Json::from(phase)),
+        ("ts", Json::from(timestamp)),
+        ("pid", Json::from(1u16)),
+        ("tid", Json::from(1u16)),
+        ("args", Json::object(args)),
+    ])
+}
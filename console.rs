@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::lock;
+
+// Byte stream the machine uses for software interrupts and instruction traces
+pub trait Console {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+    // Returns the number of bytes read, 0 at end of input
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize>;
+
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+// Process stdout and stdin
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdConsole;
+
+impl Console for StdConsole {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        io::stdout().write_all(bytes)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        // Make prompts visible before blocking on input
+        io::stdout().flush()?;
+        io::stdin().read(buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+// In-memory input and output. Clones share the same buffers, so the host can keep one handle
+// to feed input and inspect output while the machine owns another, on any thread
+#[derive(Debug, Clone, Default)]
+pub struct BufferConsole {
+    input: Arc<Mutex<VecDeque<u8>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl BufferConsole {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_input(input: &[u8]) -> Self {
+        let console = Self::new();
+        console.push_input(input);
+        console
+    }
+
+    pub fn push_input(&self, input: &[u8]) {
+        lock(&self.input).extend(input);
+    }
+
+    pub fn output(&self) -> Vec<u8> {
+        lock(&self.output).clone()
+    }
+
+    pub fn output_string(&self) -> String {
+        String::from_utf8_lossy(&lock(&self.output)).into_owned()
+    }
+
+    pub fn take_output(&self) -> Vec<u8> {
+        lock(&self.output).split_off(0)
+    }
+}
+
+impl Console for BufferConsole {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        lock(&self.output).extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut input = lock(&self.input);
+        let length = buffer.len().min(input.len());
+
+        for (target, byte) in buffer.iter_mut().zip(input.drain(..length)) {
+            *target = byte;
+        }
+
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Discards output and is always at end of input
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullConsole;
+
+impl Console for NullConsole {
+    fn write(&mut self, _bytes: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn read(&mut self, _buffer: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
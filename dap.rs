@@ -0,0 +1,582 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::asm::{assemble_with_symbols, Assembly};
+use crate::console::BufferConsole;
+use crate::history::HISTORY_LIMIT;
+use crate::instruction::{Instruction, Opcode};
+use crate::json::Json;
+use crate::operand::Operand;
+use crate::outcome::Outcome;
+use crate::{Rsmisc, INSTRUCTION_SIZE};
+
+// Debug Adapter Protocol server. The program runs synchronously, so requests are only handled
+// while it is stopped and a continue cannot be paused. Console output is sent as output events
+// and console input is always at its end
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+const STACK_REFERENCE: u64 = 2;
+
+// Serves one session, returning when the client disconnects or closes `input`
+pub fn serve<R: BufRead, W: Write>(mut input: R, output: W) -> io::Result<()> {
+    let mut adapter = Adapter {
+        output,
+        sequence: 0,
+        program: None,
+        stop_on_entry: false,
+    };
+
+    while let Some(message) = read_message(&mut input)? {
+        if message.get("type").and_then(Json::as_str) == Some("request")
+            && !adapter.handle(&message)?
+        {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Json>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or_default()];
+    input.read_exact(&mut body)?;
+
+    Json::parse(&String::from_utf8_lossy(&body))
+        .map(Some)
+        .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+struct Program {
+    vm: Rsmisc,
+    console: BufferConsole,
+    labels: BTreeMap<String, u16>,
+    // Assembly source the program was built from, for source mapping
+    source: Option<Source>,
+    // Breakpoints set from source lines, replaced by every setBreakpoints
+    source_breakpoints: Vec<u16>,
+}
+
+struct Source {
+    path: String,
+    lines: BTreeMap<u16, usize>,
+}
+
+enum Step {
+    Continue,
+    In,
+    Over,
+    Out,
+    Back,
+    ReverseContinue,
+}
+
+struct Adapter<W> {
+    output: W,
+    sequence: u64,
+    program: Option<Program>,
+    stop_on_entry: bool,
+}
+
+impl<W: Write> Adapter<W> {
+    // Returns false when the session ends
+    fn handle(&mut self, request: &Json) -> io::Result<bool> {
+        let command = request
+            .get("command")
+            .and_then(Json::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let arguments = request.get("arguments").cloned().unwrap_or(Json::Null);
+
+        let result = match command.as_str() {
+            "initialize" => Ok(Json::object(vec![
+                ("supportsConfigurationDoneRequest", Json::from(true)),
+                ("supportsSteppingGranularity", Json::from(false)),
+                ("supportsStepBack", Json::from(true)),
+            ])),
+            "launch" => self.launch(&arguments),
+            "setBreakpoints" => self.set_breakpoints(&arguments),
+            "setExceptionBreakpoints" | "configurationDone" | "pause" => Ok(Json::Null),
+            "threads" => Ok(Json::object(vec![(
+                "threads",
+                Json::from(vec![Json::object(vec![
+                    ("id", Json::from(THREAD_ID)),
+                    ("name", Json::from("main")),
+                ])]),
+            )])),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(self.scopes()),
+            "variables" => self.variables(&arguments),
+            "continue" => self
+                .program()
+                .map(|_| Json::object(vec![("allThreadsContinued", Json::from(true))])),
+            "next" | "stepIn" | "stepOut" | "stepBack" | "reverseContinue" => {
+                self.program().map(|_| Json::Null)
+            }
+            "disconnect" | "terminate" => Ok(Json::Null),
+            _ => Err(format!("unsupported request '{}'", command)),
+        };
+
+        let success = result.is_ok();
+        self.respond(request, &command, result)?;
+
+        if !success {
+            return Ok(true);
+        }
+
+        // Events that follow the response
+        match command.as_str() {
+            "launch" => self.event("initialized", Json::Null)?,
+            "configurationDone" if self.stop_on_entry => self.stopped("entry", None)?,
+            "configurationDone" | "continue" => self.resume(Step::Continue)?,
+            "next" => self.resume(Step::Over)?,
+            "stepIn" => self.resume(Step::In)?,
+            "stepOut" => self.resume(Step::Out)?,
+            "stepBack" => self.resume(Step::Back)?,
+            "reverseContinue" => self.resume(Step::ReverseContinue)?,
+            "pause" => self.stopped("pause", None)?,
+            "disconnect" | "terminate" => return Ok(false),
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<Json, String> {
+        let path = arguments
+            .get("program")
+            .and_then(Json::as_str)
+            .ok_or("missing 'program'")?;
+
+        self.program = Some(open(path)?);
+        self.stop_on_entry = arguments
+            .get("stopOnEntry")
+            .and_then(Json::as_bool)
+            .unwrap_or(false);
+
+        Ok(Json::Null)
+    }
+
+    fn program(&mut self) -> Result<&mut Program, String> {
+        self.program
+            .as_mut()
+            .ok_or_else(|| "no program is running".to_string())
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Json) -> Result<Json, String> {
+        let path = arguments
+            .get("source")
+            .and_then(|source| source.get("path"))
+            .and_then(Json::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let requested: Vec<u64> = arguments
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|breakpoint| breakpoint.get("line").and_then(Json::as_u64))
+            .collect();
+        let program = self.program()?;
+        let lines = match &program.source {
+            Some(source) if same_file(&source.path, &path) => Some(&source.lines),
+            _ => None,
+        };
+        let mut breakpoints = Vec::new();
+
+        // Breakpoints in other files can't be placed and leave the program's breakpoints alone
+        if lines.is_some() {
+            for address in program.source_breakpoints.drain(..) {
+                program.vm.remove_breakpoint(address);
+            }
+        }
+
+        for line in requested {
+            // The first instruction at or after the line
+            let location = lines.and_then(|lines| {
+                lines
+                    .iter()
+                    .filter(|(_, number)| **number as u64 >= line)
+                    .min_by_key(|(address, number)| (**number, **address))
+                    .map(|(address, number)| (*address, *number))
+            });
+
+            breakpoints.push(match location {
+                Some((address, number)) => {
+                    program.vm.add_breakpoint(address);
+                    program.source_breakpoints.push(address);
+
+                    Json::object(vec![
+                        ("verified", Json::from(true)),
+                        ("line", Json::from(number)),
+                        (
+                            "instructionReference",
+                            Json::from(format!("0x{:04X}", address)),
+                        ),
+                    ])
+                }
+                None if lines.is_some() => Json::object(vec![
+                    ("verified", Json::from(false)),
+                    ("line", Json::from(line)),
+                    (
+                        "message",
+                        Json::from("no instruction at or after this line"),
+                    ),
+                ]),
+                None => Json::object(vec![
+                    ("verified", Json::from(false)),
+                    ("line", Json::from(line)),
+                    ("message", Json::from("not the launched program")),
+                ]),
+            });
+        }
+
+        Ok(Json::object(vec![("breakpoints", Json::from(breakpoints))]))
+    }
+
+    // The current instruction, then the CALL of every return address on the call stack
+    fn stack_trace(&mut self) -> Result<Json, String> {
+        let program = self.program()?;
+        let mut addresses = vec![program.vm.ip()];
+
+        addresses.extend(
+            program
+                .vm
+                .call_stack()
+                .iter()
+                .rev()
+                .map(|address| address.wrapping_sub(INSTRUCTION_SIZE as u16)),
+        );
+
+        let frames: Vec<Json> = addresses
+            .iter()
+            .enumerate()
+            .map(|(index, address)| program.frame(index, *address))
+            .collect();
+
+        Ok(Json::object(vec![
+            ("totalFrames", Json::from(frames.len())),
+            ("stackFrames", Json::from(frames)),
+        ]))
+    }
+
+    fn scopes(&self) -> Json {
+        let scope = |name: &str, reference: u64| {
+            Json::object(vec![
+                ("name", Json::from(name)),
+                ("variablesReference", Json::from(reference)),
+                ("expensive", Json::from(false)),
+            ])
+        };
+
+        Json::object(vec![(
+            "scopes",
+            Json::from(vec![
+                scope("Registers", REGISTERS_REFERENCE),
+                scope("Stack", STACK_REFERENCE),
+            ]),
+        )])
+    }
+
+    fn variables(&mut self, arguments: &Json) -> Result<Json, String> {
+        let reference = arguments.get("variablesReference").and_then(Json::as_u64);
+        let vm = &self.program()?.vm;
+        let variable = |name: String, value: u16| {
+            Json::object(vec![
+                ("name", Json::from(name)),
+                ("value", Json::from(format!("0x{:04X}", value))),
+                ("variablesReference", Json::from(0u64)),
+            ])
+        };
+
+        let variables: Vec<Json> = match reference {
+            Some(REGISTERS_REFERENCE) => [
+                Operand::R1,
+                Operand::R2,
+                Operand::R3,
+                Operand::R4,
+                Operand::IP,
+            ]
+            .iter()
+            .filter_map(|register| {
+                let value = vm.register(*register)?;
+                Some(variable(format!("{:?}", register), value))
+            })
+            .collect(),
+            // Top of the stack first
+            Some(STACK_REFERENCE) => vm
+                .stack()
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(index, value)| variable(format!("[{}]", index), *value))
+                .collect(),
+            _ => return Err("unknown variables reference".to_string()),
+        };
+
+        Ok(Json::object(vec![("variables", Json::from(variables))]))
+    }
+
+    fn resume(&mut self, step: Step) -> io::Result<()> {
+        let program = match self.program.as_mut() {
+            Some(program) => program,
+            None => return Ok(()),
+        };
+        let vm = &mut program.vm;
+        let depth = vm.call_stack().len();
+        let is_call = matches!(
+            vm.load_48(vm.ip()),
+            Ok(tword) if Instruction::from(tword).op_code == Opcode::CALL
+        );
+
+        let outcome = match step {
+            Step::Continue => vm.run(u64::MAX).outcome,
+            Step::In => step_until(vm, |_| true),
+            Step::Over if is_call => step_until(vm, |vm| vm.call_stack().len() <= depth),
+            Step::Over => step_until(vm, |_| true),
+            Step::Out => step_until(vm, |vm| vm.call_stack().len() < depth),
+            Step::Back => vm.run_back(1).outcome,
+            Step::ReverseContinue => vm.run_back(u64::MAX).outcome,
+        };
+        let output = program.console.take_output();
+
+        if !output.is_empty() {
+            self.event(
+                "output",
+                Json::object(vec![
+                    ("category", Json::from("stdout")),
+                    (
+                        "output",
+                        Json::from(String::from_utf8_lossy(&output).into_owned()),
+                    ),
+                ]),
+            )?;
+        }
+
+        match outcome {
+            Outcome::Halted => {
+                self.event("exited", Json::object(vec![("exitCode", Json::from(0u64))]))?;
+                self.event("terminated", Json::Null)
+            }
+            Outcome::StepLimitReached => self.stopped("step", None),
+            Outcome::Breakpoint(_) => self.stopped("breakpoint", None),
+            Outcome::Watchpoint { .. } => self.stopped("data breakpoint", None),
+            Outcome::Fault(error) => self.stopped("exception", Some(error.to_string())),
+            Outcome::StartOfHistory => {
+                self.stopped("step", Some("start of the recorded history".to_string()))
+            }
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) -> io::Result<()> {
+        self.event(
+            "stopped",
+            Json::object(vec![
+                ("reason", Json::from(reason)),
+                ("threadId", Json::from(THREAD_ID)),
+                ("allThreadsStopped", Json::from(true)),
+                ("text", Json::from(text)),
+            ]),
+        )
+    }
+
+    fn respond(
+        &mut self,
+        request: &Json,
+        command: &str,
+        result: Result<Json, String>,
+    ) -> io::Result<()> {
+        let request_sequence = request.get("seq").cloned().unwrap_or(Json::Null);
+        let mut fields = vec![
+            ("type", Json::from("response")),
+            ("request_seq", request_sequence),
+            ("command", Json::from(command)),
+        ];
+
+        match result {
+            Ok(Json::Null) => fields.push(("success", Json::from(true))),
+            Ok(body) => {
+                fields.push(("success", Json::from(true)));
+                fields.push(("body", body));
+            }
+            Err(message) => {
+                fields.push(("success", Json::from(false)));
+                fields.push(("message", Json::from(message)));
+            }
+        }
+
+        self.send(fields)
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        let mut fields = vec![("type", Json::from("event")), ("event", Json::from(event))];
+
+        if body != Json::Null {
+            fields.push(("body", body));
+        }
+
+        self.send(fields)
+    }
+
+    fn send(&mut self, fields: Vec<(&str, Json)>) -> io::Result<()> {
+        self.sequence += 1;
+
+        let mut message = vec![("seq", Json::from(self.sequence))];
+        message.extend(fields);
+
+        let body = Json::object(message).to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+}
+
+impl Program {
+    fn frame(&self, index: usize, address: u16) -> Json {
+        // Named after the closest label before the address
+        let name = self
+            .labels
+            .iter()
+            .filter(|(_, value)| **value <= address)
+            .max_by_key(|(_, value)| **value)
+            .map_or_else(|| format!("0x{:04X}", address), |(label, _)| label.clone());
+        let line = self
+            .source
+            .as_ref()
+            .and_then(|source| Some((source, *source.lines.get(&address)?)));
+
+        let mut fields = vec![
+            ("id", Json::from(index)),
+            ("name", Json::from(name)),
+            (
+                "instructionPointerReference",
+                Json::from(format!("0x{:04X}", address)),
+            ),
+        ];
+
+        match line {
+            Some((source, line)) => {
+                let name = Path::new(&source.path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned());
+
+                fields.push((
+                    "source",
+                    Json::object(vec![
+                        ("name", Json::from(name)),
+                        ("path", Json::from(source.path.as_str())),
+                    ]),
+                ));
+                fields.push(("line", Json::from(line)));
+                fields.push(("column", Json::from(1u64)));
+            }
+            None => {
+                fields.push(("line", Json::from(0u64)));
+                fields.push(("column", Json::from(0u64)));
+            }
+        }
+
+        Json::object(fields)
+    }
+}
+
+// Single steps until `done` or something stops the machine, a step ending at a breakpoint
+// reports it
+fn step_until<F: Fn(&Rsmisc) -> bool>(vm: &mut Rsmisc, done: F) -> Outcome {
+    loop {
+        match vm.run(1).outcome {
+            Outcome::StepLimitReached | Outcome::Breakpoint(_) => {}
+            outcome => return outcome,
+        }
+
+        if done(vm) {
+            return Outcome::StepLimitReached;
+        }
+        if vm.breakpoints().any(|address| address == vm.ip()) {
+            return Outcome::Breakpoint(vm.ip());
+        }
+    }
+}
+
+// Whether a program file is assembly source (.asm, .s) rather than a raw image
+pub fn is_source(path: &str) -> bool {
+    path.ends_with(".asm") || path.ends_with(".s")
+}
+
+// Reads a program file for the debuggers. Sources are assembled with their symbols, anything else
+// is a raw image without any
+pub fn load(path: &str) -> Result<Assembly, String> {
+    if is_source(path) {
+        let source = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+
+        assemble_with_symbols(&source).map_err(|error| error.to_string())
+    } else {
+        let image = std::fs::read(path).map_err(|error| error.to_string())?;
+
+        Ok(Assembly {
+            image,
+            labels: BTreeMap::new(),
+            source_lines: BTreeMap::new(),
+        })
+    }
+}
+
+// Assembly sources are mapped to their lines
+fn open(path: &str) -> Result<Program, String> {
+    let assembly = load(path)?;
+    let source = if is_source(path) {
+        Some(Source {
+            path: path.to_string(),
+            lines: assembly.source_lines,
+        })
+    } else {
+        None
+    };
+
+    let console = BufferConsole::new();
+    let mut vm = Rsmisc::new(&assembly.image).map_err(|error| error.to_string())?;
+    vm.set_console(Box::new(console.clone()));
+    vm.set_history_limit(HISTORY_LIMIT);
+
+    Ok(Program {
+        vm,
+        console,
+        labels: assembly.labels,
+        source,
+        source_breakpoints: Vec::new(),
+    })
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
@@ -0,0 +1,470 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+
+use crate::instruction::{Instruction, Opcode};
+use crate::operand::{Operand, OperandType};
+use crate::{INSTRUCTION_SIZE, MEMORY_SIZE};
+
+#[derive(Debug, Clone)]
+pub enum LineKind {
+    Instruction(Instruction),
+    Data,
+}
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub kind: LineKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    // In address order
+    pub lines: Vec<Line>,
+    // Generated label for every branch target that starts a line
+    pub labels: BTreeMap<u16, String>,
+    // Memory address (MA operand) -> addresses of the instructions referencing it
+    pub references: BTreeMap<u16, Vec<u16>>,
+    // Jumps whose destination is computed at run time -> likely destination, if one was guessed
+    pub computed_jumps: BTreeMap<u16, Option<u16>>,
+}
+
+// Linear sweep: every 6 bytes of the image are decoded as an instruction and any trailing bytes
+// that do not form a whole instruction are listed as data
+pub fn disassemble(image: &[u8]) -> Disassembly {
+    let image = &image[..image.len().min(MEMORY_SIZE)];
+    let mut lines = Vec::new();
+    let mut address = 0;
+
+    while address + INSTRUCTION_SIZE <= image.len() {
+        lines.push(instruction_line(image, address));
+        address += INSTRUCTION_SIZE;
+    }
+
+    push_data(&mut lines, image, address, image.len());
+
+    Disassembly::new(lines)
+}
+
+// Recursive traversal: only instructions reachable from the entry point, following the control
+// flow of `Rsmisc`, are decoded and everything else is listed as data
+pub fn disassemble_recursive(image: &[u8], entry: u16) -> Disassembly {
+    let image = &image[..image.len().min(MEMORY_SIZE)];
+    let mut code = BTreeSet::new();
+    let mut claimed = vec![false; image.len()];
+    let mut computed_jumps = BTreeMap::new();
+    let mut pending = vec![(entry as usize, [None; 4])];
+
+    while let Some((address, mut registers)) = pending.pop() {
+        if address + INSTRUCTION_SIZE > image.len()
+            || code.contains(&address)
+            || claimed[address..address + INSTRUCTION_SIZE]
+                .iter()
+                .any(|claimed| *claimed)
+        {
+            continue;
+        }
+
+        code.insert(address);
+        claimed[address..address + INSTRUCTION_SIZE]
+            .iter_mut()
+            .for_each(|claimed| *claimed = true);
+
+        let instruction = decode(&image[address..address + INSTRUCTION_SIZE]);
+        let flow = control_flow(&instruction, address, &registers);
+
+        if flow.computed {
+            computed_jumps.insert(address as u16, flow.guess);
+        }
+
+        track_registers(&instruction, &mut registers);
+
+        for successor in flow.successors {
+            pending.push((successor, registers));
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut address = 0;
+
+    while address < image.len() {
+        if code.contains(&address) {
+            lines.push(instruction_line(image, address));
+            address += INSTRUCTION_SIZE;
+        } else {
+            let end = (address..image.len())
+                .find(|address| code.contains(address))
+                .unwrap_or(image.len());
+
+            push_data(&mut lines, image, address, end);
+            address = end;
+        }
+    }
+
+    let mut result = Disassembly::new(lines);
+
+    for guess in computed_jumps.values().flatten() {
+        if result.line_at(*guess).is_some() {
+            result.labels.insert(*guess, label_name(*guess));
+        }
+    }
+
+    result.computed_jumps = computed_jumps;
+    result
+}
+
+struct ControlFlow {
+    successors: Vec<usize>,
+    computed: bool,
+    guess: Option<u16>,
+}
+
+// Mirrors `Rsmisc::execute_next`: the IP advances by one instruction after every instruction,
+// including BZ, MOV and ULD to IP, so those resume one instruction past the written address.
+// CALL compensates for the advance, but RET does not, so execution continues two instructions
+// after the CALL
+fn control_flow(
+    instruction: &Instruction,
+    address: usize,
+    registers: &[Option<u16>; 4],
+) -> ControlFlow {
+    let next = address + INSTRUCTION_SIZE;
+    let after = |target: u16| target as usize + INSTRUCTION_SIZE;
+    let mut flow = ControlFlow {
+        successors: Vec::new(),
+        computed: false,
+        guess: None,
+    };
+
+    match instruction.op_code {
+        Opcode::HALT | Opcode::RET => {}
+        Opcode::CALL => {
+            flow.successors.push(next + INSTRUCTION_SIZE);
+
+            match resolve(instruction.target, instruction.target_imm, registers) {
+                Resolved::Constant(target) => flow.successors.push(target as usize),
+                Resolved::Guess(target) => {
+                    flow.successors.push(target as usize);
+                    flow.computed = true;
+                    flow.guess = Some(target);
+                }
+                Resolved::Unknown => flow.computed = true,
+            }
+        }
+        Opcode::BZ => {
+            let taken = match instruction.target {
+                Operand::CT => instruction.target_imm == 0,
+                _ => true,
+            };
+            let not_taken = match instruction.target {
+                Operand::CT => instruction.target_imm != 0,
+                _ => true,
+            };
+
+            if not_taken {
+                flow.successors.push(next);
+            }
+
+            if taken {
+                match resolve(instruction.source, instruction.source_imm, registers) {
+                    Resolved::Constant(target) => flow.successors.push(after(target)),
+                    Resolved::Guess(target) => {
+                        flow.successors.push(after(target));
+                        flow.computed = true;
+                        flow.guess = Some(target);
+                    }
+                    Resolved::Unknown => flow.computed = true,
+                }
+            }
+        }
+        Opcode::MOV if instruction.target == Operand::IP => {
+            match resolve(instruction.source, instruction.source_imm, registers) {
+                Resolved::Constant(target) => flow.successors.push(after(target)),
+                Resolved::Guess(target) => {
+                    flow.successors.push(after(target));
+                    flow.computed = true;
+                    flow.guess = Some(target);
+                }
+                Resolved::Unknown => flow.computed = true,
+            }
+        }
+        Opcode::ULD if instruction.target == Operand::IP => flow.computed = true,
+        _ => flow.successors.push(next),
+    }
+
+    flow
+}
+
+enum Resolved {
+    Constant(u16),
+    Guess(u16),
+    Unknown,
+}
+
+// Register operands are guessed from the last constant moved into the register on the path
+// that reached the jump. IP and memory operands depend on run-time state and stay unknown
+fn resolve(operand: Operand, immediate: u16, registers: &[Option<u16>; 4]) -> Resolved {
+    let guess = match operand {
+        Operand::CT => return Resolved::Constant(immediate),
+        Operand::R1 => registers[0],
+        Operand::R2 => registers[1],
+        Operand::R3 => registers[2],
+        Operand::R4 => registers[3],
+        Operand::IP | Operand::MA => None,
+    };
+
+    match guess {
+        Some(target) => Resolved::Guess(target),
+        None => Resolved::Unknown,
+    }
+}
+
+fn track_registers(instruction: &Instruction, registers: &mut [Option<u16>; 4]) {
+    let index = match instruction.target {
+        Operand::R1 => 0,
+        Operand::R2 => 1,
+        Operand::R3 => 2,
+        Operand::R4 => 3,
+        _ => 4,
+    };
+
+    match instruction.op_code {
+        Opcode::MOV if index < 4 => {
+            registers[index] = match instruction.source {
+                Operand::CT => Some(instruction.source_imm),
+                Operand::R1 => registers[0],
+                Operand::R2 => registers[1],
+                Operand::R3 => registers[2],
+                Operand::R4 => registers[3],
+                Operand::IP | Operand::MA => None,
+            }
+        }
+        Opcode::ULD if index < 4 => registers[index] = None,
+        // Subroutines and interrupt handlers may change any register
+        Opcode::CALL | Opcode::SWI => *registers = [None; 4],
+        _ => {}
+    }
+}
+
+fn instruction_line(image: &[u8], address: usize) -> Line {
+    let bytes = image[address..address + INSTRUCTION_SIZE].to_vec();
+
+    Line {
+        address: address as u16,
+        kind: LineKind::Instruction(decode(&bytes)),
+        bytes,
+    }
+}
+
+// Data is listed as 16-bit words, three to a line, with a lone trailing byte on its own line
+fn push_data(lines: &mut Vec<Line>, image: &[u8], start: usize, end: usize) {
+    let mut address = start;
+
+    while address < end {
+        let length = match (end - address).min(INSTRUCTION_SIZE) {
+            1 => 1,
+            length => length & !1,
+        };
+
+        lines.push(Line {
+            address: address as u16,
+            bytes: image[address..address + length].to_vec(),
+            kind: LineKind::Data,
+        });
+        address += length;
+    }
+}
+
+// Operand holding the destination of a CALL, BZ or MOV to IP
+pub fn branch_operand(instruction: &Instruction) -> Option<OperandType> {
+    match instruction.op_code {
+        Opcode::CALL => Some(OperandType::TARGET),
+        Opcode::BZ => Some(OperandType::SOURCE),
+        Opcode::MOV if instruction.target == Operand::IP => Some(OperandType::SOURCE),
+        _ => None,
+    }
+}
+
+// Destination written in the instruction when it is a constant. Note that BZ and MOV to IP
+// resume at the instruction after this address, since the IP is advanced after every instruction
+pub fn branch_target(instruction: &Instruction) -> Option<u16> {
+    match branch_operand(instruction)? {
+        OperandType::TARGET if instruction.target == Operand::CT => Some(instruction.target_imm),
+        OperandType::SOURCE if instruction.source == Operand::CT => Some(instruction.source_imm),
+        _ => None,
+    }
+}
+
+// Addresses read or written through MA operands
+pub fn memory_references(instruction: &Instruction) -> Vec<u16> {
+    let operands = [
+        (instruction.target, instruction.target_imm),
+        (instruction.source, instruction.source_imm),
+    ];
+
+    operands
+        .iter()
+        .take(instruction.op_code.operand_count())
+        .filter(|(operand, _)| *operand == Operand::MA)
+        .map(|(_, immediate)| *immediate)
+        .collect()
+}
+
+pub fn label_name(address: u16) -> String {
+    format!("L_{:04X}", address)
+}
+
+fn decode(bytes: &[u8]) -> Instruction {
+    let tword = bytes
+        .iter()
+        .fold(0u64, |tword, byte| (tword << 8) | *byte as u64);
+
+    Instruction::from(tword)
+}
+
+impl Disassembly {
+    pub fn new(lines: Vec<Line>) -> Self {
+        let starts: BTreeSet<u16> = lines.iter().map(|line| line.address).collect();
+        let mut labels = BTreeMap::new();
+        let mut references: BTreeMap<u16, Vec<u16>> = BTreeMap::new();
+
+        for line in &lines {
+            if let LineKind::Instruction(instruction) = &line.kind {
+                if let Some(target) = branch_target(instruction) {
+                    if starts.contains(&target) {
+                        labels.insert(target, label_name(target));
+                    }
+                }
+
+                for address in memory_references(instruction) {
+                    references.entry(address).or_default().push(line.address);
+                }
+            }
+        }
+
+        Disassembly {
+            lines,
+            labels,
+            references,
+            computed_jumps: BTreeMap::new(),
+        }
+    }
+
+    pub fn line_at(&self, address: u16) -> Option<&Line> {
+        self.lines
+            .binary_search_by_key(&address, |line| line.address)
+            .ok()
+            .map(|index| &self.lines[index])
+    }
+
+    // Same syntax as `Display for Instruction`, with branch targets replaced by their labels
+    pub fn render(&self, instruction: &Instruction) -> String {
+        let branch = branch_operand(instruction);
+        let operands = [
+            (
+                OperandType::TARGET,
+                instruction.target,
+                instruction.target_imm,
+            ),
+            (
+                OperandType::SOURCE,
+                instruction.source,
+                instruction.source_imm,
+            ),
+        ];
+        let mut text = instruction.op_code.mnemonic().to_string();
+
+        for (operand_type, operand, immediate) in
+            operands.iter().take(instruction.op_code.operand_count())
+        {
+            text.push(' ');
+
+            match self.labels.get(immediate) {
+                Some(label) if Some(*operand_type) == branch && *operand == Operand::CT => {
+                    text.push('#');
+                    text.push_str(label);
+                }
+                _ => text.push_str(&operand.display(*immediate)),
+            }
+        }
+
+        text
+    }
+
+    fn render_data(&self, bytes: &[u8]) -> String {
+        if bytes.len() == 1 {
+            return format!(".byte {:X}", bytes[0]);
+        }
+
+        let values: Vec<String> = bytes
+            .chunks(2)
+            .map(|word| format!("{:X}", (word[0] as u16) << 8 | word[1] as u16))
+            .collect();
+
+        format!(".word {}", values.join(" "))
+    }
+
+    fn render_comment(&self, address: u16) -> String {
+        match self.computed_jumps.get(&address) {
+            Some(Some(guess)) => match self.labels.get(guess) {
+                Some(label) => format!("  ; unresolved, likely {}", label),
+                None => format!("  ; unresolved, likely {:X}", guess),
+            },
+            Some(None) => "  ; unresolved".to_string(),
+            None => String::new(),
+        }
+    }
+}
+
+impl Display for Disassembly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            if let Some(label) = self.labels.get(&line.address) {
+                writeln!(f, "{}:", label)?;
+            }
+
+            let bytes: Vec<String> = line
+                .bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            let text = match &line.kind {
+                LineKind::Instruction(instruction) => self.render(instruction),
+                LineKind::Data => self.render_data(&line.bytes),
+            };
+
+            writeln!(
+                f,
+                "    {:04X}  {:<17}  {}{}",
+                line.address,
+                bytes.join(" "),
+                text,
+                self.render_comment(line.address)
+            )?;
+        }
+
+        if !self.references.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "; Cross references")?;
+
+            for (address, sources) in &self.references {
+                let sources: Vec<String> = sources
+                    .iter()
+                    .map(
+                        |source| match self.line_at(*source).map(|line| &line.kind) {
+                            Some(LineKind::Instruction(instruction)) => {
+                                format!("{:04X} {}", source, instruction.op_code.mnemonic())
+                            }
+                            _ => format!("{:04X}", source),
+                        },
+                    )
+                    .collect();
+
+                writeln!(f, "; {:04X}  {}", address, sources.join(", "))?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,9 @@
+// What happens when a DIV instruction divides by zero
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum DivisionByZero {
+    // Raise DIVISION_BY_ZERO
+    Fault,
+    // Call the handler the guest installed with SWI 0x2, or raise DIVISION_BY_ZERO if there is
+    // none. The handler returns with RET to the instruction after the DIV, nothing is pushed
+    Trap,
+}
@@ -0,0 +1,254 @@
+use std::fmt::Display;
+
+use crate::instruction::{DecodeError, Instruction};
+use crate::operand::Operand;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RsmiscError {
+    CallStackEmpty {
+        ip: u16,
+        instruction: Instruction,
+    },
+    UnimplementedSoftwareInterrupt {
+        ip: u16,
+        instruction: Instruction,
+        number: u16,
+    },
+    NoElementsInStack {
+        ip: u16,
+        instruction: Instruction,
+    },
+    InvalidUnloadTarget {
+        ip: u16,
+        instruction: Instruction,
+        operand: Operand,
+    },
+    InvalidMoveTarget {
+        ip: u16,
+        instruction: Instruction,
+        operand: Operand,
+    },
+    IllegalInstruction {
+        ip: u16,
+        tword: u64,
+        error: DecodeError,
+    },
+    OutOfBounds {
+        ip: u16,
+        address: u16,
+        length: usize,
+    },
+    ProgramTooLarge {
+        size: usize,
+    },
+    DivisionByZero {
+        ip: u16,
+        instruction: Instruction,
+    },
+    Io {
+        ip: u16,
+        kind: std::io::ErrorKind,
+        message: String,
+    },
+    InvalidNumber {
+        ip: u16,
+        text: String,
+    },
+    SoftwareInterruptFault {
+        ip: u16,
+        instruction: Instruction,
+        number: u16,
+        message: String,
+    },
+    StringOutOfBounds {
+        ip: u16,
+        address: u16,
+    },
+    InvalidRadix {
+        ip: u16,
+        radix: u16,
+    },
+    OutOfGas {
+        ip: u16,
+        instruction: Instruction,
+        required: u64,
+        remaining: u64,
+    },
+    NotARegister {
+        operand: Operand,
+    },
+    NotInHistory {
+        count: u64,
+    },
+    InvalidSnapshot {
+        message: String,
+    },
+}
+
+impl RsmiscError {
+    pub fn io(ip: u16, error: std::io::Error) -> Self {
+        RsmiscError::Io {
+            ip,
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+
+    // Numeric code used before errors were typed
+    pub fn code(&self) -> i32 {
+        match self {
+            RsmiscError::CallStackEmpty { .. } => -2,
+            RsmiscError::UnimplementedSoftwareInterrupt { .. } => -3,
+            RsmiscError::NoElementsInStack { .. } => -4,
+            RsmiscError::InvalidUnloadTarget { .. } => -5,
+            RsmiscError::InvalidMoveTarget { .. } => -6,
+            RsmiscError::IllegalInstruction { .. } => -7,
+            RsmiscError::OutOfBounds { .. } => -8,
+            RsmiscError::ProgramTooLarge { .. } => -9,
+            RsmiscError::DivisionByZero { .. } => -10,
+            RsmiscError::Io { .. } => -11,
+            RsmiscError::InvalidNumber { .. } => -12,
+            RsmiscError::SoftwareInterruptFault { .. } => -13,
+            RsmiscError::StringOutOfBounds { .. } => -14,
+            RsmiscError::InvalidRadix { .. } => -15,
+            RsmiscError::OutOfGas { .. } => -16,
+            RsmiscError::NotARegister { .. } => -17,
+            RsmiscError::NotInHistory { .. } => -18,
+            RsmiscError::InvalidSnapshot { .. } => -19,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RsmiscError::CallStackEmpty { .. } => "CALL_STACK_EMPTY",
+            RsmiscError::UnimplementedSoftwareInterrupt { .. } => {
+                "UNIMPLEMENTED_SOFTWARE_INTERRUPT"
+            }
+            RsmiscError::NoElementsInStack { .. } => "NO_ELEMENTS_IN_STACK",
+            RsmiscError::InvalidUnloadTarget { .. } => "INVALID_UNLOAD_TARGET",
+            RsmiscError::InvalidMoveTarget { .. } => "INVALID_MOVE_TARGET",
+            RsmiscError::IllegalInstruction { .. } => "ILLEGAL_INSTRUCTION",
+            RsmiscError::OutOfBounds { .. } => "OUT_OF_BOUNDS",
+            RsmiscError::ProgramTooLarge { .. } => "PROGRAM_TOO_LARGE",
+            RsmiscError::DivisionByZero { .. } => "DIVISION_BY_ZERO",
+            RsmiscError::Io { .. } => "IO_ERROR",
+            RsmiscError::InvalidNumber { .. } => "INVALID_NUMBER",
+            RsmiscError::SoftwareInterruptFault { .. } => "SOFTWARE_INTERRUPT_FAULT",
+            RsmiscError::StringOutOfBounds { .. } => "STRING_OUT_OF_BOUNDS",
+            RsmiscError::InvalidRadix { .. } => "INVALID_RADIX",
+            RsmiscError::OutOfGas { .. } => "OUT_OF_GAS",
+            RsmiscError::NotARegister { .. } => "NOT_A_REGISTER",
+            RsmiscError::NotInHistory { .. } => "NOT_IN_HISTORY",
+            RsmiscError::InvalidSnapshot { .. } => "INVALID_SNAPSHOT",
+        }
+    }
+
+    // Address of the instruction that faulted, if the error was raised while executing
+    pub fn ip(&self) -> Option<u16> {
+        match self {
+            RsmiscError::CallStackEmpty { ip, .. }
+            | RsmiscError::UnimplementedSoftwareInterrupt { ip, .. }
+            | RsmiscError::NoElementsInStack { ip, .. }
+            | RsmiscError::InvalidUnloadTarget { ip, .. }
+            | RsmiscError::InvalidMoveTarget { ip, .. }
+            | RsmiscError::IllegalInstruction { ip, .. }
+            | RsmiscError::OutOfBounds { ip, .. }
+            | RsmiscError::DivisionByZero { ip, .. }
+            | RsmiscError::Io { ip, .. }
+            | RsmiscError::InvalidNumber { ip, .. }
+            | RsmiscError::SoftwareInterruptFault { ip, .. }
+            | RsmiscError::StringOutOfBounds { ip, .. }
+            | RsmiscError::InvalidRadix { ip, .. }
+            | RsmiscError::OutOfGas { ip, .. } => Some(*ip),
+            RsmiscError::ProgramTooLarge { .. }
+            | RsmiscError::NotARegister { .. }
+            | RsmiscError::NotInHistory { .. }
+            | RsmiscError::InvalidSnapshot { .. } => None,
+        }
+    }
+
+    pub fn instruction(&self) -> Option<Instruction> {
+        match self {
+            RsmiscError::CallStackEmpty { instruction, .. }
+            | RsmiscError::UnimplementedSoftwareInterrupt { instruction, .. }
+            | RsmiscError::NoElementsInStack { instruction, .. }
+            | RsmiscError::InvalidUnloadTarget { instruction, .. }
+            | RsmiscError::InvalidMoveTarget { instruction, .. }
+            | RsmiscError::DivisionByZero { instruction, .. }
+            | RsmiscError::SoftwareInterruptFault { instruction, .. }
+            | RsmiscError::OutOfGas { instruction, .. } => Some(*instruction),
+            RsmiscError::IllegalInstruction { .. }
+            | RsmiscError::OutOfBounds { .. }
+            | RsmiscError::Io { .. }
+            | RsmiscError::InvalidNumber { .. }
+            | RsmiscError::StringOutOfBounds { .. }
+            | RsmiscError::InvalidRadix { .. }
+            | RsmiscError::ProgramTooLarge { .. }
+            | RsmiscError::NotARegister { .. }
+            | RsmiscError::NotInHistory { .. }
+            | RsmiscError::InvalidSnapshot { .. } => None,
+        }
+    }
+}
+
+impl Display for RsmiscError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RsmiscError::UnimplementedSoftwareInterrupt { number, .. } => {
+                write!(f, "{} 0x{:x}", self.name(), number)?
+            }
+            RsmiscError::InvalidUnloadTarget { operand, .. }
+            | RsmiscError::InvalidMoveTarget { operand, .. }
+            | RsmiscError::NotARegister { operand } => write!(f, "{} {:?}", self.name(), operand)?,
+            RsmiscError::IllegalInstruction { tword, error, .. } => {
+                write!(f, "{}: {} (0x{:012x})", self.name(), error, tword)?
+            }
+            RsmiscError::OutOfBounds {
+                address, length, ..
+            } => write!(
+                f,
+                "{}: 0x{:x} bytes at 0x{:x}",
+                self.name(),
+                length,
+                address
+            )?,
+            RsmiscError::ProgramTooLarge { size } => write!(
+                f,
+                "{}: 0x{:x} bytes (memory is 0x{:x})",
+                self.name(),
+                size,
+                crate::MEMORY_SIZE
+            )?,
+            RsmiscError::Io { message, .. } | RsmiscError::InvalidSnapshot { message } => {
+                write!(f, "{}: {}", self.name(), message)?
+            }
+            RsmiscError::SoftwareInterruptFault {
+                number, message, ..
+            } => write!(f, "{} 0x{:x}: {}", self.name(), number, message)?,
+            RsmiscError::StringOutOfBounds { address, .. } => {
+                write!(f, "{}: string at 0x{:x}", self.name(), address)?
+            }
+            RsmiscError::InvalidRadix { radix, .. } => write!(f, "{}: {}", self.name(), radix)?,
+            RsmiscError::InvalidNumber { text, .. } => write!(f, "{}: {:?}", self.name(), text)?,
+            RsmiscError::NotInHistory { count } => {
+                write!(f, "{}: instruction 0x{:x}", self.name(), count)?
+            }
+            _ => write!(f, "{}", self.name())?,
+        }
+
+        match (self.ip(), self.instruction()) {
+            (Some(ip), Some(instruction)) => write!(f, " (at 0x{:x}: {})", ip, instruction),
+            (Some(ip), None) => write!(f, " (at 0x{:x})", ip),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for RsmiscError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RsmiscError::IllegalInstruction { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use crate::instruction::{Instruction, Opcode};
+use crate::operand::Operand;
+
+// Gas charged for an instruction: the cost of its opcode plus the cost of each operand it uses,
+// which depends on whether the operand is a register (R1 to R4, IP), a constant (CT) or a memory
+// address (MA)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CostTable {
+    opcodes: HashMap<Opcode, u64>,
+    pub register: u64,
+    pub constant: u64,
+    pub memory: u64,
+}
+
+impl CostTable {
+    // Every instruction and operand costs nothing until configured
+    pub fn zero() -> Self {
+        CostTable {
+            opcodes: Opcode::ALL.iter().map(|op_code| (*op_code, 0)).collect(),
+            register: 0,
+            constant: 0,
+            memory: 0,
+        }
+    }
+
+    // One unit per instruction, with a charge for multiplication, division and memory operands
+    pub fn new() -> Self {
+        let mut table = CostTable::zero();
+
+        for op_code in Opcode::ALL.iter() {
+            table.set_opcode(*op_code, 1);
+        }
+
+        table.set_opcode(Opcode::MUL, 4);
+        table.set_opcode(Opcode::DIV, 8);
+        table.memory = 2;
+        table
+    }
+
+    pub fn set_opcode(&mut self, op_code: Opcode, cost: u64) {
+        self.opcodes.insert(op_code, cost);
+    }
+
+    pub fn opcode(&self, op_code: Opcode) -> u64 {
+        self.opcodes.get(&op_code).copied().unwrap_or(0)
+    }
+
+    pub fn operand(&self, operand: Operand) -> u64 {
+        match operand {
+            Operand::R1 | Operand::R2 | Operand::R3 | Operand::R4 | Operand::IP => self.register,
+            Operand::CT => self.constant,
+            Operand::MA => self.memory,
+        }
+    }
+
+    pub fn cost(&self, instruction: &Instruction) -> u64 {
+        let operands = [instruction.target, instruction.source];
+
+        operands
+            .iter()
+            .take(instruction.op_code.operand_count())
+            .fold(self.opcode(instruction.op_code), |cost, operand| {
+                cost.saturating_add(self.operand(*operand))
+            })
+    }
+}
+
+impl Default for CostTable {
+    fn default() -> Self {
+        CostTable::new()
+    }
+}
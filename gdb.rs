@@ -0,0 +1,466 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+
+use crate::operand::Operand;
+use crate::outcome::Outcome;
+use crate::watch::{Access, WatchHit, Watchpoint};
+use crate::{Rsmisc, RsmiscError};
+
+// GDB Remote Serial Protocol server. Registers are R1, R2, R3, R4 and IP, sent as 16-bit
+// big-endian values like words in memory, so GDB needs `set endian big`
+
+// Target description served through qXfer:features:read
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rsmisc.core">
+    <reg name="r1" bitsize="16" type="uint16" regnum="0"/>
+    <reg name="r2" bitsize="16" type="uint16"/>
+    <reg name="r3" bitsize="16" type="uint16"/>
+    <reg name="r4" bitsize="16" type="uint16"/>
+    <reg name="ip" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+// GDB register numbers
+const REGISTERS: [Operand; 0x5] = [
+    Operand::R1,
+    Operand::R2,
+    Operand::R3,
+    Operand::R4,
+    Operand::IP,
+];
+
+// Largest packet accepted, advertised in qSupported. Memory replies use two hex digits per
+// byte, so GDB never asks for more than half of it at once
+const PACKET_SIZE: usize = 0x4000;
+
+// Signals reported in stop replies
+const SIGILL: u8 = 0x4;
+const SIGTRAP: u8 = 0x5;
+const SIGABRT: u8 = 0x6;
+const SIGFPE: u8 = 0x8;
+const SIGSEGV: u8 = 0xb;
+
+// Accepts one connection and serves it until GDB detaches or disconnects
+pub fn listen_tcp<A: ToSocketAddrs>(vm: &mut Rsmisc, address: A) -> io::Result<()> {
+    let (stream, _) = TcpListener::bind(address)?.accept()?;
+    stream.set_nodelay(true)?;
+
+    serve(vm, stream)
+}
+
+#[cfg(unix)]
+pub fn listen_unix<P: AsRef<std::path::Path>>(vm: &mut Rsmisc, path: P) -> io::Result<()> {
+    let (stream, _) = std::os::unix::net::UnixListener::bind(path)?.accept()?;
+
+    serve(vm, stream)
+}
+
+// Serves one session on `stream`. A continue runs until a breakpoint, a watchpoint, HALT or a
+// fault, GDB cannot interrupt it. Reverse execution needs `Rsmisc::set_history_limit`
+pub fn serve<S: Read + Write>(vm: &mut Rsmisc, stream: S) -> io::Result<()> {
+    let mut session = Session {
+        vm,
+        stream,
+        acknowledge: true,
+    };
+
+    while let Some(packet) = session.receive()? {
+        let reply = session.reply(&packet);
+        session.send(&reply)?;
+
+        // GDB closes the connection after kill and detach
+        if packet == "k" || packet.starts_with('D') {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+struct Session<'a, S> {
+    vm: &'a mut Rsmisc,
+    stream: S,
+    acknowledge: bool,
+}
+
+impl<'a, S: Read + Write> Session<'a, S> {
+    // Next packet with a valid checksum, `None` when the connection closes
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                // Acknowledgements and interrupts outside a continue are ignored
+                Some(_) => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+
+            let mut checksum = [0; 0x2];
+            for digit in checksum.iter_mut() {
+                *digit = match self.read_byte()? {
+                    Some(byte) => byte,
+                    None => return Ok(None),
+                };
+            }
+
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(checksum_of(&data));
+
+            if self.acknowledge {
+                if !valid {
+                    self.stream.write_all(b"-")?;
+                    continue;
+                }
+
+                self.stream.write_all(b"+")?;
+            }
+
+            return Ok(Some(String::from_utf8_lossy(&unescape(&data)).into_owned()));
+        }
+    }
+
+    fn send(&mut self, reply: &str) -> io::Result<()> {
+        let data = escape(reply.as_bytes());
+        let mut packet = Vec::with_capacity(data.len() + 4);
+
+        packet.push(b'$');
+        packet.extend_from_slice(&data);
+        packet.extend_from_slice(format!("#{:02x}", checksum_of(&data)).as_bytes());
+
+        loop {
+            self.stream.write_all(&packet)?;
+            self.stream.flush()?;
+
+            if !self.acknowledge {
+                return Ok(());
+            }
+
+            // Resend until GDB acknowledges the packet
+            match self.read_byte()? {
+                Some(b'-') => {}
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0; 0x1];
+
+        loop {
+            match self.stream.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    // Reply to a packet, empty for unsupported packets
+    fn reply(&mut self, packet: &str) -> String {
+        let (command, arguments) = packet.split_at(packet.len().min(1));
+
+        match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => self.read_registers(),
+            "G" => result(self.write_registers(arguments)),
+            "p" => self.read_register(arguments).unwrap_or_else(|()| error()),
+            "P" => result(self.write_register(arguments)),
+            "m" => self.read_memory(arguments).unwrap_or_else(|()| error()),
+            "M" => result(self.write_memory(arguments)),
+            "Z" => result(self.set_breakpoint(arguments, true)),
+            "z" => result(self.set_breakpoint(arguments, false)),
+            "s" => self.resume(arguments, 1),
+            "c" => self.resume(arguments, u64::MAX),
+            "b" if arguments == "s" => stop_reply(&self.vm.run_back(1).outcome),
+            "b" if arguments == "c" => stop_reply(&self.vm.run_back(u64::MAX).outcome),
+            "H" | "D" | "k" => "OK".to_string(),
+            "q" | "Q" => self.query(packet),
+            _ => String::new(),
+        }
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+;ReverseStep+;\
+                 ReverseContinue+",
+                PACKET_SIZE
+            );
+        }
+        if packet == "QStartNoAckMode" {
+            self.acknowledge = false;
+            return "OK".to_string();
+        }
+        if packet == "qAttached" {
+            return "1".to_string();
+        }
+        if let Some(arguments) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return transfer(TARGET_XML, arguments).unwrap_or_else(|()| error());
+        }
+
+        String::new()
+    }
+
+    fn register(&self, number: usize) -> Result<u16, ()> {
+        REGISTERS
+            .get(number)
+            .and_then(|register| self.vm.register(*register))
+            .ok_or(())
+    }
+
+    fn set_register(&mut self, number: usize, value: u16) -> Result<(), ()> {
+        let register = REGISTERS.get(number).ok_or(())?;
+
+        self.vm.set_register(*register, value).map_err(|_| ())
+    }
+
+    fn read_registers(&self) -> String {
+        REGISTERS
+            .iter()
+            .filter_map(|register| self.vm.register(*register))
+            .map(|value| format!("{:04x}", value))
+            .collect()
+    }
+
+    fn write_registers(&mut self, arguments: &str) -> Result<(), ()> {
+        let bytes = decode_hex(arguments)?;
+
+        if bytes.len() != REGISTERS.len() * 2 {
+            return Err(());
+        }
+
+        for (number, value) in bytes.chunks(2).enumerate() {
+            self.set_register(number, u16::from_be_bytes([value[0], value[1]]))?;
+        }
+
+        Ok(())
+    }
+
+    fn read_register(&self, arguments: &str) -> Result<String, ()> {
+        let value = self.register(parse_hex(arguments)?)?;
+
+        Ok(format!("{:04x}", value))
+    }
+
+    fn write_register(&mut self, arguments: &str) -> Result<(), ()> {
+        let (number, value) = arguments.split_once('=').ok_or(())?;
+        let value = decode_hex(value)?;
+
+        if value.len() != 2 {
+            return Err(());
+        }
+
+        self.set_register(parse_hex(number)?, u16::from_be_bytes([value[0], value[1]]))
+    }
+
+    fn read_memory(&self, arguments: &str) -> Result<String, ()> {
+        let (address, length) = arguments.split_once(',').ok_or(())?;
+        let length = parse_hex(length)?;
+
+        if length > PACKET_SIZE / 2 {
+            return Err(());
+        }
+
+        let bytes = self
+            .vm
+            .read_memory(parse_address(address)?, length)
+            .map_err(|_| ())?;
+
+        Ok(encode_hex(bytes))
+    }
+
+    fn write_memory(&mut self, arguments: &str) -> Result<(), ()> {
+        let (range, data) = arguments.split_once(':').ok_or(())?;
+        let (address, length) = range.split_once(',').ok_or(())?;
+        let bytes = decode_hex(data)?;
+
+        if bytes.len() != parse_hex(length)? {
+            return Err(());
+        }
+
+        self.vm
+            .write_memory(parse_address(address)?, &bytes)
+            .map_err(|_| ())
+    }
+
+    // Z0 and z0 are software breakpoints, Z2 to Z4 write, read and access watchpoints
+    fn set_breakpoint(&mut self, arguments: &str, insert: bool) -> Result<(), ()> {
+        let mut fields = arguments.split(',');
+        let kind = fields.next().ok_or(())?;
+        let address = parse_address(fields.next().ok_or(())?)?;
+        let length = parse_hex(fields.next().ok_or(())?)?;
+
+        let access = match kind {
+            "0" if insert => {
+                self.vm.add_breakpoint(address);
+                return Ok(());
+            }
+            "0" => {
+                self.vm.remove_breakpoint(address);
+                return Ok(());
+            }
+            "2" => Access::Write,
+            "3" => Access::Read,
+            "4" => Access::ReadWrite,
+            _ => return Err(()),
+        };
+        let end = address
+            .checked_add(length.saturating_sub(1) as u16)
+            .ok_or(())?;
+        let watchpoint = Watchpoint::new(address, end, access);
+
+        if insert {
+            self.vm.add_watchpoint(watchpoint);
+        } else {
+            self.vm.remove_watchpoint(watchpoint);
+        }
+
+        Ok(())
+    }
+
+    // `s` and `c` with an optional address to resume at
+    fn resume(&mut self, arguments: &str, limit: u64) -> String {
+        if !arguments.is_empty() {
+            match parse_address(arguments) {
+                Ok(address) => self.vm.set_ip(address),
+                Err(()) => return error(),
+            }
+        }
+
+        stop_reply(&self.vm.run(limit).outcome)
+    }
+}
+
+fn stop_reply(outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::Halted => "W00".to_string(),
+        Outcome::StepLimitReached | Outcome::Breakpoint(_) => format!("S{:02x}", SIGTRAP),
+        Outcome::StartOfHistory => format!("T{:02x}replaylog:begin;", SIGTRAP),
+        Outcome::Watchpoint { hits, .. } => {
+            let memory = hits.iter().find_map(|hit| match hit {
+                WatchHit::Memory { address, access } => Some((*address, *access)),
+                WatchHit::Register { .. } => None,
+            });
+
+            match memory {
+                Some((address, Access::Read)) => {
+                    format!("T{:02x}rwatch:{:x};", SIGTRAP, address)
+                }
+                Some((address, _)) => format!("T{:02x}watch:{:x};", SIGTRAP, address),
+                None => format!("S{:02x}", SIGTRAP),
+            }
+        }
+        Outcome::Fault(fault) => {
+            let signal = match fault {
+                RsmiscError::IllegalInstruction { .. } => SIGILL,
+                RsmiscError::DivisionByZero { .. } => SIGFPE,
+                RsmiscError::OutOfBounds { .. } | RsmiscError::StringOutOfBounds { .. } => SIGSEGV,
+                _ => SIGABRT,
+            };
+
+            format!("S{:02x}", signal)
+        }
+    }
+}
+
+// qXfer reply for `offset,length` of `document`
+fn transfer(document: &str, arguments: &str) -> Result<String, ()> {
+    let (offset, length) = arguments.split_once(',').ok_or(())?;
+    let offset = parse_hex(offset)?.min(document.len());
+    let end = offset
+        .saturating_add(parse_hex(length)?)
+        .min(document.len());
+    let prefix = if end == document.len() { 'l' } else { 'm' };
+
+    Ok(format!(
+        "{}{}",
+        prefix,
+        document.get(offset..end).ok_or(())?
+    ))
+}
+
+fn result(result: Result<(), ()>) -> String {
+    match result {
+        Ok(()) => "OK".to_string(),
+        Err(()) => error(),
+    }
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+// `}` escapes the next byte, XORed with 0x20
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'}' => result.extend(bytes.next().map(|byte| byte ^ 0x20)),
+            byte => result.push(*byte),
+        }
+    }
+
+    result
+}
+
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+
+    for byte in data {
+        match byte {
+            b'$' | b'#' | b'}' | b'*' => result.extend_from_slice(&[b'}', byte ^ 0x20]),
+            byte => result.push(*byte),
+        }
+    }
+
+    result
+}
+
+fn parse_hex(text: &str) -> Result<usize, ()> {
+    usize::from_str_radix(text, 16).map_err(|_| ())
+}
+
+fn parse_address(text: &str) -> Result<u16, ()> {
+    u16::from_str_radix(text, 16).map_err(|_| ())
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// is_multiple_of needs Rust 1.87
+#[allow(clippy::manual_is_multiple_of)]
+fn decode_hex(text: &str) -> Result<Vec<u8>, ()> {
+    if text.len() % 2 != 0 {
+        return Err(());
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|index| {
+            text.get(index..index + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or(())
+        })
+        .collect()
+}
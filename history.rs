@@ -0,0 +1,27 @@
+// Instructions the debuggers record for stepping back unless told otherwise
+pub const HISTORY_LIMIT: usize = 0x10000;
+
+// State one instruction changed, holding the values from before it ran. An instruction pushes
+// or pops at most one value on each stack, so the previous length and top are enough to undo it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delta {
+    pub ip: u16,
+    pub registers: [u16; 0x4],
+    pub stack_length: usize,
+    pub stack_top: Option<u16>,
+    pub call_stack_length: usize,
+    pub call_stack_top: Option<u16>,
+    // Previous value of every byte written, in the order of the writes
+    pub memory: Vec<(u16, u8)>,
+    pub trap_handler: Option<u16>,
+    pub gas: Option<u64>,
+}
+
+// Restores a stack to its length and top before an instruction pushed or popped
+pub(crate) fn restore_stack(stack: &mut Vec<u16>, length: usize, top: Option<u16>) {
+    stack.truncate(length);
+
+    if stack.len() < length {
+        stack.extend(top);
+    }
+}
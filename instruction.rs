@@ -1,8 +1,8 @@
 use std::fmt::Display;
 
-use crate::operand::Operand;
+use crate::operand::{Operand, OperandType, OPERAND_COUNT};
 
-#[derive(Copy, Debug, Clone)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Opcode {
     HALT,
     ADD,
@@ -19,7 +19,165 @@ pub enum Opcode {
     NOP,
 }
 
-#[derive(Copy, Debug, Clone)]
+impl Opcode {
+    pub const ALL: [Opcode; 13] = [
+        Opcode::HALT,
+        Opcode::ADD,
+        Opcode::SUB,
+        Opcode::MUL,
+        Opcode::DIV,
+        Opcode::MOV,
+        Opcode::LD,
+        Opcode::ULD,
+        Opcode::BZ,
+        Opcode::SWI,
+        Opcode::CALL,
+        Opcode::RET,
+        Opcode::NOP,
+    ];
+
+    // Any byte the decoder does not recognize also decodes to NOP
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            Opcode::HALT => 0x0,
+            Opcode::ADD => 0x1,
+            Opcode::SUB => 0x2,
+            Opcode::MUL => 0x3,
+            Opcode::DIV => 0x4,
+            Opcode::MOV => 0x5,
+            Opcode::LD => 0x6,
+            Opcode::ULD => 0x7,
+            Opcode::BZ => 0x8,
+            Opcode::SWI => 0x9,
+            Opcode::CALL => 0xA,
+            Opcode::RET => 0xB,
+            Opcode::NOP => 0xC,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        match byte {
+            0x0 => Some(Opcode::HALT),
+            0x1 => Some(Opcode::ADD),
+            0x2 => Some(Opcode::SUB),
+            0x3 => Some(Opcode::MUL),
+            0x4 => Some(Opcode::DIV),
+            0x5 => Some(Opcode::MOV),
+            0x6 => Some(Opcode::LD),
+            0x7 => Some(Opcode::ULD),
+            0x8 => Some(Opcode::BZ),
+            0x9 => Some(Opcode::SWI),
+            0xA => Some(Opcode::CALL),
+            0xB => Some(Opcode::RET),
+            0xC => Some(Opcode::NOP),
+            _ => None,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Opcode::HALT => "HALT",
+            Opcode::ADD => "ADD",
+            Opcode::SUB => "SUB",
+            Opcode::MUL => "MUL",
+            Opcode::DIV => "DIV",
+            Opcode::MOV => "MOV",
+            Opcode::LD => "LD",
+            Opcode::ULD => "ULD",
+            Opcode::BZ => "BZ",
+            Opcode::SWI => "SWI",
+            Opcode::CALL => "CALL",
+            Opcode::RET => "RET",
+            Opcode::NOP => "NOP",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Opcode> {
+        match mnemonic.to_ascii_uppercase().as_str() {
+            "HALT" => Some(Opcode::HALT),
+            "ADD" => Some(Opcode::ADD),
+            "SUB" => Some(Opcode::SUB),
+            "MUL" => Some(Opcode::MUL),
+            "DIV" => Some(Opcode::DIV),
+            "MOV" => Some(Opcode::MOV),
+            "LD" => Some(Opcode::LD),
+            "ULD" => Some(Opcode::ULD),
+            "BZ" => Some(Opcode::BZ),
+            "SWI" => Some(Opcode::SWI),
+            "CALL" => Some(Opcode::CALL),
+            "RET" => Some(Opcode::RET),
+            "NOP" => Some(Opcode::NOP),
+            _ => None,
+        }
+    }
+
+    // Number of operands written after the mnemonic (target first, then source)
+    pub fn operand_count(&self) -> usize {
+        match self {
+            Opcode::HALT | Opcode::RET | Opcode::NOP => 0,
+            Opcode::LD | Opcode::ULD | Opcode::SWI | Opcode::CALL => 1,
+            Opcode::ADD | Opcode::SUB | Opcode::MUL | Opcode::DIV | Opcode::MOV | Opcode::BZ => 2,
+        }
+    }
+
+    // Whether the operand form can be executed in the given position. Positions the opcode
+    // does not use are ignored by the machine, so every form is accepted there
+    pub fn allows(&self, operand_type: OperandType, operand: Operand) -> bool {
+        let position = match operand_type {
+            OperandType::TARGET => 0,
+            OperandType::SOURCE => 1,
+        };
+
+        if position >= self.operand_count() {
+            return true;
+        }
+
+        match (self, operand_type, operand) {
+            (Opcode::MOV, OperandType::TARGET, Operand::CT) => false,
+            (Opcode::MOV, OperandType::TARGET, Operand::MA) => false,
+            (Opcode::ULD, OperandType::TARGET, Operand::CT) => false,
+            (Opcode::SWI, OperandType::TARGET, operand) => operand == Operand::CT,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    UnknownOpcode(u8),
+    OperandCombinationOutOfRange(u8),
+    OperandNotAllowed {
+        op_code: Opcode,
+        operand_type: OperandType,
+        operand: Operand,
+    },
+}
+
+impl std::error::Error for DecodeError {}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode(op) => write!(f, "unknown opcode 0x{:x}", op),
+            DecodeError::OperandCombinationOutOfRange(operand_combination) => write!(
+                f,
+                "operand combination 0x{:x} out of range",
+                operand_combination
+            ),
+            DecodeError::OperandNotAllowed {
+                op_code,
+                operand_type,
+                operand,
+            } => write!(
+                f,
+                "{:?} is not a valid {:?} operand for {:?}",
+                operand, operand_type, op_code
+            ),
+        }
+    }
+}
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
 pub struct Instruction {
     pub op_code: Opcode,
     pub target: Operand,
@@ -28,6 +186,63 @@ pub struct Instruction {
     pub source_imm: u16,
 }
 
+impl Instruction {
+    // Inverse of `Instruction::from(u64)`, immediates are stored byte-swapped
+    pub fn encode(&self) -> u64 {
+        let op = self.op_code.to_byte() as u64;
+        let operand_combination = Operand::combination(self.target, self.source) as u64;
+        let target_imm = self.target_imm.swap_bytes() as u64;
+        let source_imm = self.source_imm.swap_bytes() as u64;
+
+        (op << 40) | (operand_combination << 32) | (target_imm << 16) | source_imm
+    }
+
+    // Encoded instruction in memory order (as read by `Rsmisc::load_48`)
+    pub fn to_bytes(&self) -> [u8; 6] {
+        let bytes = self.encode().to_be_bytes();
+        let mut result = [0; 6];
+
+        result.copy_from_slice(&bytes[2..]);
+        result
+    }
+
+    // Like `Instruction::from(u64)`, but rejects unknown opcodes, operand combinations past
+    // the last valid one and operand forms the opcode cannot execute. There is no `TryFrom<u64>`
+    // because the infallible `From<u64>` already provides one
+    pub fn decode_strict(tword: u64) -> Result<Instruction, DecodeError> {
+        let op = ((tword >> 40) & 0xff) as u8;
+        let operand_combination = ((tword >> 32) & 0xff) as u8;
+
+        if Opcode::from_byte(op).is_none() {
+            return Err(DecodeError::UnknownOpcode(op));
+        }
+        if operand_combination >= OPERAND_COUNT * OPERAND_COUNT {
+            return Err(DecodeError::OperandCombinationOutOfRange(
+                operand_combination,
+            ));
+        }
+
+        let instruction = Instruction::from(tword);
+
+        for &(operand_type, operand) in [
+            (OperandType::TARGET, instruction.target),
+            (OperandType::SOURCE, instruction.source),
+        ]
+        .iter()
+        {
+            if !instruction.op_code.allows(operand_type, operand) {
+                return Err(DecodeError::OperandNotAllowed {
+                    op_code: instruction.op_code,
+                    operand_type,
+                    operand,
+                });
+            }
+        }
+
+        Ok(instruction)
+    }
+}
+
 impl From<u64> for Instruction {
     // Instruction size is 48 bits ("tword")
     fn from(tword: u64) -> Self {
@@ -37,21 +252,7 @@ impl From<u64> for Instruction {
         let source_imm = (((tword >> 8) & 0xff) | (tword & 0xff) << 8) as u16;
 
         Instruction {
-            op_code: match op {
-                0x0 => Opcode::HALT,
-                0x1 => Opcode::ADD,
-                0x2 => Opcode::SUB,
-                0x3 => Opcode::MUL,
-                0x4 => Opcode::DIV,
-                0x5 => Opcode::MOV,
-                0x6 => Opcode::LD,
-                0x7 => Opcode::ULD,
-                0x8 => Opcode::BZ,
-                0x9 => Opcode::SWI,
-                0xA => Opcode::CALL,
-                0xB => Opcode::RET,
-                _ => Opcode::NOP,
-            },
+            op_code: Opcode::from_byte(op).unwrap_or(Opcode::NOP),
             target: Operand::get_combination_target(operand_combination),
             source: Operand::get_combination_source(operand_combination),
             target_imm,
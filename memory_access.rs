@@ -0,0 +1,8 @@
+// How accesses that run past the last byte of memory are handled
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum MemoryAccess {
+    // Raise OUT_OF_BOUNDS
+    Fault,
+    // Continue from address 0x0
+    Wrap,
+}
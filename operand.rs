@@ -1,6 +1,6 @@
 use std::fmt::Debug;
 
-#[derive(Copy, Debug, Clone)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Operand {
     R1,
     R2,
@@ -11,6 +11,7 @@ pub enum Operand {
     MA,
 }
 
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
 pub enum OperandType {
     TARGET,
     SOURCE,
@@ -19,6 +20,33 @@ pub enum OperandType {
 pub const OPERAND_COUNT: u8 = 7;
 
 impl Operand {
+    pub const ALL: [Operand; OPERAND_COUNT as usize] = [
+        Operand::R1,
+        Operand::R2,
+        Operand::R3,
+        Operand::R4,
+        Operand::IP,
+        Operand::CT,
+        Operand::MA,
+    ];
+
+    pub fn index(&self) -> u8 {
+        match self {
+            Operand::R1 => 0,
+            Operand::R2 => 1,
+            Operand::R3 => 2,
+            Operand::R4 => 3,
+            Operand::IP => 4,
+            Operand::CT => 5,
+            Operand::MA => 6,
+        }
+    }
+
+    // Inverse of `get_combination_target` and `get_combination_source`
+    pub fn combination(target: Operand, source: Operand) -> u8 {
+        target.index() * OPERAND_COUNT + source.index()
+    }
+
     pub fn get_combination_target(operand_combination: u8) -> Operand {
         match operand_combination / OPERAND_COUNT {
             0 => Operand::R1,
@@ -0,0 +1,24 @@
+use crate::watch::WatchHit;
+use crate::RsmiscError;
+
+// Why `Rsmisc::run` stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    // A HALT (or a host interrupt handler) stopped the machine
+    Halted,
+    StepLimitReached,
+    // The IP reached a breakpoint, the instruction there has not been executed yet
+    Breakpoint(u16),
+    // The instruction at `ip` hit watchpoints, it has completed and the IP is past it
+    Watchpoint { ip: u16, hits: Vec<WatchHit> },
+    Fault(RsmiscError),
+    // `Rsmisc::run_back` undid every recorded instruction
+    StartOfHistory,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunResult {
+    pub outcome: Outcome,
+    // Instructions that completed during the run
+    pub steps: u64,
+}
@@ -0,0 +1,258 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use crate::calls::{CallStack, Symbols};
+use crate::disasm::{Disassembly, Line, LineKind};
+use crate::instruction::{Instruction, Opcode};
+use crate::lock;
+use crate::trace::{TraceRecord, Tracer};
+
+// Hot spots listed by the report
+const HOT_SPOTS: usize = 0x10;
+
+// Counts executed instructions per address, per opcode and per function. A function is
+// identified by the address a CALL (or trap) entered it at, and everything executed before the
+// first call belongs to a root function entered at the first profiled instruction. The CALL is
+// charged to the caller and the RET to the callee
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    state: Arc<Mutex<State>>,
+}
+
+// Instructions executed in a function, including (inclusive) or excluding (exclusive) the
+// functions it called
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCost {
+    pub entry: u16,
+    pub name: String,
+    pub inclusive: u64,
+    pub exclusive: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    symbols: Symbols,
+    total: u64,
+    addresses: BTreeMap<u16, (Instruction, u64)>,
+    opcodes: HashMap<Opcode, u64>,
+    // Entry -> inclusive and exclusive count
+    functions: BTreeMap<u16, (u64, u64)>,
+    // Entries of the active functions, outermost first -> exclusive count
+    stacks: BTreeMap<Vec<u16>, u64>,
+    root: Option<u16>,
+    calls: CallStack,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_symbols(labels: &BTreeMap<String, u16>) -> Self {
+        let profile = Self::new();
+
+        lock(&profile.state).symbols = Symbols::new(labels);
+
+        profile
+    }
+
+    pub fn instructions(&self) -> u64 {
+        lock(&self.state).total
+    }
+
+    pub fn address_count(&self, address: u16) -> u64 {
+        lock(&self.state)
+            .addresses
+            .get(&address)
+            .map_or(0, |(_, count)| *count)
+    }
+
+    pub fn opcode_count(&self, op_code: Opcode) -> u64 {
+        lock(&self.state)
+            .opcodes
+            .get(&op_code)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Most expensive first, by inclusive and then exclusive count
+    pub fn functions(&self) -> Vec<FunctionCost> {
+        let state = lock(&self.state);
+        let mut functions: Vec<FunctionCost> = state
+            .functions
+            .iter()
+            .map(|(entry, (inclusive, exclusive))| FunctionCost {
+                entry: *entry,
+                name: state.symbols.name(*entry),
+                inclusive: *inclusive,
+                exclusive: *exclusive,
+            })
+            .collect();
+
+        functions.sort_by(|a, b| {
+            (b.inclusive, b.exclusive, a.entry).cmp(&(a.inclusive, a.exclusive, b.entry))
+        });
+        functions
+    }
+
+    // One line per call stack, "outer;inner count" with the exclusive count, the input of
+    // flamegraph.pl and inferno
+    pub fn collapsed_stacks(&self) -> String {
+        let state = lock(&self.state);
+        let mut text = String::new();
+
+        for (stack, count) in &state.stacks {
+            let names: Vec<String> = stack
+                .iter()
+                .map(|entry| state.symbols.name(*entry))
+                .collect();
+            let _ = writeln!(text, "{} {}", names.join(";"), count);
+        }
+
+        text
+    }
+
+    // Functions, opcodes and hot spots sorted by cost, then the executed instructions with
+    // their counts
+    pub fn report(&self) -> String {
+        let functions = self.functions();
+        let state = lock(&self.state);
+        let percent = |count: u64| 100.0 * count as f64 / state.total.max(1) as f64;
+        let mut text = String::new();
+
+        let _ = writeln!(text, "{} instructions executed", state.total);
+
+        let _ = writeln!(text);
+        let _ = writeln!(
+            text,
+            "{:>10}  {:>6}  {:>10}  {:>6}  function",
+            "inclusive", "%", "exclusive", "%"
+        );
+        for function in &functions {
+            let _ = writeln!(
+                text,
+                "{:>10}  {:>6.2}  {:>10}  {:>6.2}  {}",
+                function.inclusive,
+                percent(function.inclusive),
+                function.exclusive,
+                percent(function.exclusive),
+                function.name
+            );
+        }
+
+        let mut opcodes: Vec<(Opcode, u64)> = Opcode::ALL
+            .iter()
+            .filter_map(|op_code| state.opcodes.get(op_code).map(|count| (*op_code, *count)))
+            .collect();
+        opcodes.sort_by_key(|(_, count)| Reverse(*count));
+
+        let _ = writeln!(text);
+        let _ = writeln!(text, "{:>10}  {:>6}  opcode", "count", "%");
+        for (op_code, count) in opcodes {
+            let _ = writeln!(
+                text,
+                "{:>10}  {:>6.2}  {}",
+                count,
+                percent(count),
+                op_code.mnemonic()
+            );
+        }
+
+        let disassembly = state.disassembly();
+        let mut hot_spots: Vec<(&u16, &(Instruction, u64))> = state.addresses.iter().collect();
+        hot_spots.sort_by_key(|(_, (_, count))| Reverse(*count));
+
+        let _ = writeln!(text);
+        let _ = writeln!(text, "{:>10}  {:>6}  hot spot", "count", "%");
+        for (address, (instruction, count)) in hot_spots.into_iter().take(HOT_SPOTS) {
+            let _ = writeln!(
+                text,
+                "{:>10}  {:>6.2}  {:04X}  {}",
+                count,
+                percent(*count),
+                address,
+                disassembly.render(instruction)
+            );
+        }
+
+        let _ = writeln!(text);
+        for (address, (instruction, count)) in &state.addresses {
+            if let Some(label) = disassembly.labels.get(address) {
+                let _ = writeln!(text, "{:>20}{}:", "", label);
+            }
+
+            let _ = writeln!(
+                text,
+                "{:>10}  {:>6.2}  {:04X}  {}",
+                count,
+                percent(*count),
+                address,
+                disassembly.render(instruction)
+            );
+        }
+
+        text
+    }
+}
+
+impl State {
+    // The executed instructions, labelled with the symbols and the function entries
+    fn disassembly(&self) -> Disassembly {
+        let lines = self
+            .addresses
+            .iter()
+            .map(|(address, (instruction, _))| Line {
+                address: *address,
+                bytes: Vec::new(),
+                kind: LineKind::Instruction(*instruction),
+            })
+            .collect();
+        let mut disassembly = Disassembly::new(lines);
+
+        for entry in self.functions.keys() {
+            disassembly.labels.insert(*entry, self.symbols.name(*entry));
+        }
+        disassembly.labels.extend(self.symbols.labels().clone());
+
+        disassembly
+    }
+}
+
+impl Tracer for Profile {
+    fn trace(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let mut state = lock(&self.state);
+
+        // Everything outside the calls seen so far belongs to the root function
+        let root = *state.root.get_or_insert(record.ip);
+        let mut frames = vec![root];
+        frames.extend_from_slice(state.calls.frames());
+
+        state.total += 1;
+        state
+            .addresses
+            .entry(record.ip)
+            .or_insert((record.instruction, 0))
+            .1 += 1;
+        *state.opcodes.entry(record.instruction.op_code).or_default() += 1;
+
+        // Recursive functions are charged once per instruction
+        let mut active = frames.clone();
+        active.sort_unstable();
+        active.dedup();
+        for entry in active {
+            state.functions.entry(entry).or_default().0 += 1;
+        }
+
+        let current = *frames.last().unwrap_or(&root);
+        state.functions.entry(current).or_default().1 += 1;
+
+        *state.stacks.entry(frames).or_default() += 1;
+
+        state.calls.follow(record);
+
+        Ok(())
+    }
+}
@@ -1,49 +1,297 @@
-use std::fmt::Display;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt::{Debug, Display};
+use std::io::{Read, Write};
+use std::sync::{Mutex, MutexGuard, PoisonError};
 
 use arithmetic_operation::ArithmeticOperation;
+use console::{Console, StdConsole};
+use division_by_zero::DivisionByZero;
+use gas::CostTable;
+use history::Delta;
 use instruction::Instruction;
+use memory_access::MemoryAccess;
 use operand::{Operand, OperandType};
+use outcome::{Outcome, RunResult};
+use swi::{SwiAction, SwiContext, SwiHandler};
+use trace::{MemoryWrite, StackChange, TraceRecord, Tracer};
+use watch::{Access, WatchHit, Watchpoint};
+
+pub use error::RsmiscError;
 
 pub mod arithmetic_operation;
+pub mod asm;
+pub mod calls;
+pub mod chrome_trace;
+pub mod console;
+pub mod dap;
+pub mod disasm;
+pub mod division_by_zero;
+pub mod error;
+pub mod gas;
+pub mod gdb;
+pub mod history;
 pub mod instruction;
+// Public for the Json returned by TraceRecord::to_json and ChromeTrace::to_json
+pub mod json;
+pub mod memory_access;
 pub mod operand;
+pub mod outcome;
+pub mod profile;
+pub mod snapshot;
+pub mod swi;
+pub mod trace;
+pub mod watch;
 
 // Size (in bytes) of the instruction
-const INSTRUCTION_SIZE: usize = 0x6;
+pub const INSTRUCTION_SIZE: usize = 0x6;
+
+// Size (in bytes) of the memory, every 16-bit address is valid
+pub const MEMORY_SIZE: usize = 0x10000;
 
-#[derive(Debug, Clone)]
+// State shared between the host and the machine cannot be left inconsistent by a panic, so
+// poisoning is ignored
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Not Clone, the console, interrupt handlers and tracers belong to the host and cannot be
+// duplicated. `save_snapshot` and `load_snapshot` copy the machine state into another instance
 pub struct Rsmisc {
-    memory: [u8; 0xffff], // 64 KiB
+    memory: Vec<u8>, // 64 KiB
     ip: u16,
     registers: [u16; 0x4], // R1, R2, R3, R4,
     stack: Vec<u16>,
     call_stack: Vec<u16>,
+    strict_decoding: bool,
+    memory_access: MemoryAccess,
+    division_by_zero: DivisionByZero,
+    trap_handler: Option<u16>,
+    console: Box<dyn Console + Send>,
+    swi_handlers: HashMap<u16, Box<dyn SwiHandler + Send>>,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    watched_registers: Vec<Operand>,
+    // Hits of the instruction being executed, the host's own loads and stores record none
+    watch_hits: Vec<WatchHit>,
+    gas: Option<u64>,
+    cost_table: CostTable,
+    instruction_count: u64,
+    history: VecDeque<Delta>,
+    history_limit: usize,
+    // Bytes the instruction being executed overwrote, while recording
+    memory_log: Vec<(u16, u8)>,
+    tracers: Vec<Box<dyn Tracer + Send>>,
 }
 
 impl Rsmisc {
-    pub fn new(program: &Vec<u8>) -> Result<Self, RsmiscError> {
+    pub fn new(program: &[u8]) -> Result<Self, RsmiscError> {
+        if program.len() > MEMORY_SIZE {
+            return Err(RsmiscError::ProgramTooLarge {
+                size: program.len(),
+            });
+        }
+
         let mut result = Self {
-            memory: [0; 0xffff],
+            memory: vec![0; MEMORY_SIZE],
             ip: 0,
             registers: [0; 0x4],
             stack: Vec::new(),
             call_stack: Vec::new(),
+            strict_decoding: false,
+            memory_access: MemoryAccess::Fault,
+            division_by_zero: DivisionByZero::Fault,
+            trap_handler: None,
+            console: Box::new(StdConsole),
+            swi_handlers: HashMap::new(),
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            watched_registers: Vec::new(),
+            watch_hits: Vec::new(),
+            gas: None,
+            cost_table: CostTable::new(),
+            instruction_count: 0,
+            history: VecDeque::new(),
+            history_limit: 0,
+            memory_log: Vec::new(),
+            tracers: Vec::new(),
         };
-        let length = program.len();
+        result.memory[..program.len()].copy_from_slice(program);
+
+        Ok(result)
+    }
+
+    pub fn ip(&self) -> u16 {
+        self.ip
+    }
+
+    pub fn set_ip(&mut self, ip: u16) {
+        self.ip = ip;
+    }
+
+    // R1, R2, R3, R4
+    pub fn registers(&self) -> [u16; 0x4] {
+        self.registers
+    }
 
-        for address in 0..length {
-            result.memory[address] = program[address];
+    // Value of R1 to R4 or IP, `None` for CT and MA
+    pub fn register(&self, operand: Operand) -> Option<u16> {
+        match operand {
+            Operand::R1 => Some(self.registers[0]),
+            Operand::R2 => Some(self.registers[1]),
+            Operand::R3 => Some(self.registers[2]),
+            Operand::R4 => Some(self.registers[3]),
+            Operand::IP => Some(self.ip),
+            Operand::CT | Operand::MA => None,
         }
+    }
 
-        Ok(result)
+    pub fn set_register(&mut self, operand: Operand, value: u16) -> Result<(), RsmiscError> {
+        match operand {
+            Operand::R1 => self.registers[0] = value,
+            Operand::R2 => self.registers[1] = value,
+            Operand::R3 => self.registers[2] = value,
+            Operand::R4 => self.registers[3] = value,
+            Operand::IP => self.ip = value,
+            Operand::CT | Operand::MA => return Err(RsmiscError::NotARegister { operand }),
+        }
+
+        Ok(())
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // Memory ranges never wrap around the end of memory
+    pub fn read_memory(&self, address: u16, length: usize) -> Result<&[u8], RsmiscError> {
+        let start = address as usize;
+
+        // `start` is at most 0xFFFF so this cannot underflow, while adding could overflow
+        if length > MEMORY_SIZE - start {
+            return Err(RsmiscError::OutOfBounds {
+                ip: self.ip,
+                address,
+                length,
+            });
+        }
+
+        Ok(&self.memory[start..start + length])
+    }
+
+    pub fn write_memory(&mut self, address: u16, bytes: &[u8]) -> Result<(), RsmiscError> {
+        let start = address as usize;
+
+        if bytes.len() > MEMORY_SIZE - start {
+            return Err(RsmiscError::OutOfBounds {
+                ip: self.ip,
+                address,
+                length: bytes.len(),
+            });
+        }
+
+        self.memory[start..start + bytes.len()].copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    // Data stack, the last element is the top
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    // Return addresses pushed by CALL, the last element is the top
+    pub fn call_stack(&self) -> &[u16] {
+        &self.call_stack
+    }
+
+    // When enabled, invalid instructions raise ILLEGAL_INSTRUCTION instead of running as NOP
+    pub fn set_strict_decoding(&mut self, strict: bool) {
+        self.strict_decoding = strict;
+    }
+
+    pub fn strict_decoding(&self) -> bool {
+        self.strict_decoding
+    }
+
+    // Accesses running past 0xFFFF fault by default
+    pub fn set_memory_access(&mut self, memory_access: MemoryAccess) {
+        self.memory_access = memory_access;
+    }
+
+    pub fn memory_access(&self) -> MemoryAccess {
+        self.memory_access
+    }
+
+    // Software interrupts and instruction traces use stdout and stdin by default
+    pub fn set_console(&mut self, console: Box<dyn Console + Send>) {
+        self.console = console;
+    }
+
+    // Registers a host handler for SWI `number`, replacing any built-in interrupt with that number
+    pub fn register_swi<H>(&mut self, number: u16, handler: H)
+    where
+        H: SwiHandler + Send + 'static,
+    {
+        self.swi_handlers.insert(number, Box::new(handler));
+    }
+
+    pub fn unregister_swi(&mut self, number: u16) -> bool {
+        self.swi_handlers.remove(&number).is_some()
+    }
+
+    // `None` (the default) disables metering. Otherwise every instruction is charged from the
+    // cost table before it runs, and one that cannot be paid for raises OUT_OF_GAS instead
+    pub fn set_gas(&mut self, gas: Option<u64>) {
+        self.gas = gas;
+    }
+
+    pub fn remaining_gas(&self) -> Option<u64> {
+        self.gas
+    }
+
+    pub fn set_cost_table(&mut self, cost_table: CostTable) {
+        self.cost_table = cost_table;
+    }
+
+    pub fn cost_table(&self) -> &CostTable {
+        &self.cost_table
+    }
+
+    // Division by zero faults by default
+    pub fn set_division_by_zero(&mut self, division_by_zero: DivisionByZero) {
+        self.division_by_zero = division_by_zero;
+    }
+
+    pub fn division_by_zero(&self) -> DivisionByZero {
+        self.division_by_zero
+    }
+
+    // Memory index of the byte at `offset` from `address`
+    fn memory_index(
+        &self,
+        address: u16,
+        offset: usize,
+        length: usize,
+    ) -> Result<usize, RsmiscError> {
+        let index = address as usize + offset;
+
+        match self.memory_access {
+            _ if index < MEMORY_SIZE => Ok(index),
+            MemoryAccess::Wrap => Ok(index % MEMORY_SIZE),
+            MemoryAccess::Fault => Err(RsmiscError::OutOfBounds {
+                ip: self.ip,
+                address,
+                length,
+            }),
+        }
     }
 
     pub fn load_48(&self, address: u16) -> Result<u64, RsmiscError> {
         let mut result = 0;
 
         for index in 0..INSTRUCTION_SIZE {
-            let current = self.memory[address as usize + index] as u64;
-            result |= current << (INSTRUCTION_SIZE - (index + 1)) * 8;
+            let current = self.memory[self.memory_index(address, index, INSTRUCTION_SIZE)?] as u64;
+            result |= current << ((INSTRUCTION_SIZE - (index + 1)) * 8);
         }
 
         Ok(result)
@@ -53,27 +301,89 @@ impl Rsmisc {
         let mut result = 0;
 
         for index in 0..2 {
-            let current = self.memory[address as usize + index] as u16;
-            result |= current << (2 - (index + 1)) * 8;
+            let current = self.memory[self.memory_index(address, index, 2)?] as u16;
+            result |= current << ((2 - (index + 1)) * 8);
         }
 
         Ok(result)
     }
 
-    pub fn store_16(&mut self, address: u16, value: u16) -> () {
+    pub fn store_16(&mut self, address: u16, value: u16) -> Result<(), RsmiscError> {
         let b0 = (value & 0xff00) >> 8;
         let b1 = value & 0xff;
+        let i0 = self.memory_index(address, 0, 2)?;
+        let i1 = self.memory_index(address, 1, 2)?;
+
+        self.write_byte(i0, b0 as u8);
+        self.write_byte(i1, b1 as u8);
 
-        self.memory[(address + 0) as usize] = b0 as u8;
-        self.memory[(address + 1) as usize] = b1 as u8;
+        Ok(())
+    }
+
+    // Writes a byte during execution, remembering the previous value while recording
+    fn write_byte(&mut self, index: usize, byte: u8) {
+        if self.recording() {
+            self.memory_log.push((index as u16, self.memory[index]));
+        }
+
+        self.memory[index] = byte;
     }
 
     pub fn execute_next(&mut self, print: bool) -> Result<bool, RsmiscError> {
+        self.watch_hits.clear();
+
         // Fetch the instruction
-        let instruction = Instruction::from(self.load_48(self.ip)?);
+        let tword = self.load_48(self.ip)?;
+        let instruction = if self.strict_decoding {
+            match Instruction::decode_strict(tword) {
+                Ok(instruction) => instruction,
+                Err(error) => {
+                    return Err(RsmiscError::IllegalInstruction {
+                        ip: self.ip,
+                        tword,
+                        error,
+                    })
+                }
+            }
+        } else {
+            Instruction::from(tword)
+        };
+
+        let delta = if self.recording() {
+            Some(Delta {
+                ip: self.ip,
+                registers: self.registers,
+                stack_length: self.stack.len(),
+                stack_top: self.stack.last().copied(),
+                call_stack_length: self.call_stack.len(),
+                call_stack_top: self.call_stack.last().copied(),
+                memory: Vec::new(),
+                trap_handler: self.trap_handler,
+                gas: self.gas,
+            })
+        } else {
+            None
+        };
+
+        // Charge for the instruction, leaving the machine untouched if it cannot be paid for
+        if let Some(gas) = self.gas {
+            let cost = self.cost_table.cost(&instruction);
+
+            if cost > gas {
+                return Err(RsmiscError::OutOfGas {
+                    ip: self.ip,
+                    instruction,
+                    required: cost,
+                    remaining: gas,
+                });
+            }
+
+            self.gas = Some(gas - cost);
+        }
 
         // Execute the instruction
-        let result = match instruction.op_code {
+        let before = self.register_snapshot();
+        let mut result = match instruction.op_code {
             instruction::Opcode::HALT => self.halt(instruction, print),
             instruction::Opcode::ADD => self.add(instruction, print),
             instruction::Opcode::SUB => self.sub(instruction, print),
@@ -90,14 +400,356 @@ impl Rsmisc {
         };
 
         // Increment the instruction pointer
-        self.ip += INSTRUCTION_SIZE as u16;
+        self.ip = self.ip.wrapping_add(INSTRUCTION_SIZE as u16);
+
+        if result.is_ok() {
+            self.watch_registers(&before);
+        }
+
+        // Instructions that fault are counted and recorded too, they may have changed the state
+        self.instruction_count += 1;
+
+        if let Some(mut delta) = delta {
+            delta.memory = std::mem::take(&mut self.memory_log);
+
+            if !self.tracers.is_empty() {
+                let record = self.trace_record(tword, instruction, &delta, &result);
+
+                for tracer in self.tracers.iter_mut() {
+                    if let Err(error) = tracer.trace(&record) {
+                        // The fault of the instruction itself takes precedence
+                        if result.is_ok() {
+                            result = Err(RsmiscError::io(delta.ip, error));
+                        }
+                    }
+                }
+            }
+
+            if self.history_limit > 0 {
+                self.history.push_back(delta);
+
+                if self.history.len() > self.history_limit {
+                    self.history.pop_front();
+                }
+            }
+        }
+
+        result
+    }
+
+    // Whether `execute_next` records what each instruction changes, for the history or tracers
+    fn recording(&self) -> bool {
+        self.history_limit > 0 || !self.tracers.is_empty()
+    }
+
+    fn trace_record(
+        &self,
+        tword: u64,
+        instruction: Instruction,
+        delta: &Delta,
+        result: &Result<bool, RsmiscError>,
+    ) -> TraceRecord {
+        let memory_writes = delta
+            .memory
+            .iter()
+            .enumerate()
+            .map(|(position, (address, old))| {
+                // The next write to the same byte holds the value this one wrote
+                let new = delta.memory[position + 1..]
+                    .iter()
+                    .find(|(later, _)| later == address)
+                    .map_or(self.memory[*address as usize], |(_, value)| *value);
+
+                MemoryWrite {
+                    address: *address,
+                    old: *old,
+                    new,
+                }
+            })
+            .collect();
+
+        TraceRecord {
+            count: self.instruction_count,
+            ip: delta.ip,
+            tword,
+            instruction,
+            registers_before: delta.registers,
+            registers_after: self.registers,
+            ip_after: self.ip,
+            stack: StackChange::between(delta.stack_length, delta.stack_top, &self.stack),
+            call_stack: StackChange::between(
+                delta.call_stack_length,
+                delta.call_stack_top,
+                &self.call_stack,
+            ),
+            memory_writes,
+            swi: match instruction.op_code {
+                instruction::Opcode::SWI => Some(instruction.target_imm),
+                _ => None,
+            },
+            error: result.as_ref().err().cloned(),
+        }
+    }
+
+    // Executes up to `limit` instructions. A breakpoint at the IP when the run starts is not
+    // reported, so calling `run` again resumes from a breakpoint
+    pub fn run(&mut self, limit: u64) -> RunResult {
+        let mut steps = 0;
+
+        let outcome = loop {
+            if steps >= limit {
+                break Outcome::StepLimitReached;
+            }
+            if steps > 0 && self.breakpoints.contains(&self.ip) {
+                break Outcome::Breakpoint(self.ip);
+            }
+
+            let ip = self.ip;
+
+            match self.execute_next(false) {
+                Ok(true) => {
+                    steps += 1;
+
+                    let hits = self.take_watch_hits();
+                    if !hits.is_empty() {
+                        break Outcome::Watchpoint { ip, hits };
+                    }
+                }
+                Ok(false) => {
+                    steps += 1;
+                    break Outcome::Halted;
+                }
+                Err(error) => break Outcome::Fault(error),
+            }
+        };
+
+        RunResult { outcome, steps }
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    // Writes the machine state in the format described in `snapshot`
+    pub fn save_snapshot<W: Write>(&self, mut writer: W) -> Result<(), RsmiscError> {
+        writer
+            .write_all(&snapshot::encode(self))
+            .map_err(|error| RsmiscError::io(self.ip, error))
+    }
+
+    // Replaces the machine state with a snapshot. The console, interrupt handlers, breakpoints
+    // and watchpoints are kept, the history is cleared
+    pub fn load_snapshot<R: Read>(&mut self, mut reader: R) -> Result<(), RsmiscError> {
+        let mut bytes = Vec::new();
+
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|error| RsmiscError::io(self.ip, error))?;
 
-        return result;
+        snapshot::decode(self, &bytes)
     }
 
-    pub fn halt(&self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
+    // Instructions executed since the machine was created, including those that faulted
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    // Number of executed instructions recorded for `step_back`, 0 (the default) disables
+    // recording. Console input and output cannot be undone
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+
+        while self.history.len() > limit {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn history_limit(&self) -> usize {
+        self.history_limit
+    }
+
+    // Every instruction `execute_next` runs is reported to the tracers, in the order they were
+    // added
+    pub fn add_tracer<T>(&mut self, tracer: T)
+    where
+        T: Tracer + Send + 'static,
+    {
+        self.tracers.push(Box::new(tracer));
+    }
+
+    pub fn clear_tracers(&mut self) {
+        self.tracers.clear();
+    }
+
+    // Recorded instructions, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &Delta> + '_ {
+        self.history.iter()
+    }
+
+    // Undoes the last recorded instruction, false when there is none
+    pub fn step_back(&mut self) -> bool {
+        let delta = match self.history.pop_back() {
+            Some(delta) => delta,
+            None => return false,
+        };
+
+        for (address, byte) in delta.memory.iter().rev() {
+            self.memory[*address as usize] = *byte;
+        }
+
+        history::restore_stack(&mut self.stack, delta.stack_length, delta.stack_top);
+        history::restore_stack(
+            &mut self.call_stack,
+            delta.call_stack_length,
+            delta.call_stack_top,
+        );
+        self.ip = delta.ip;
+        self.registers = delta.registers;
+        self.trap_handler = delta.trap_handler;
+        self.gas = delta.gas;
+        self.instruction_count -= 1;
+
+        true
+    }
+
+    // Undoes up to `limit` instructions, stopping when the IP reaches a breakpoint or the
+    // history runs out
+    pub fn run_back(&mut self, limit: u64) -> RunResult {
+        let mut steps = 0;
+
+        let outcome = loop {
+            if steps >= limit {
+                break Outcome::StepLimitReached;
+            }
+            if !self.step_back() {
+                break Outcome::StartOfHistory;
+            }
+
+            steps += 1;
+
+            if self.breakpoints.contains(&self.ip) {
+                break Outcome::Breakpoint(self.ip);
+            }
+        };
+
+        RunResult { outcome, steps }
+    }
+
+    // Undoes instructions until `instruction_count` is `count`
+    pub fn goto_instruction(&mut self, count: u64) -> Result<(), RsmiscError> {
+        let oldest = self.instruction_count - self.history.len() as u64;
+
+        if count < oldest || count > self.instruction_count {
+            return Err(RsmiscError::NotInHistory { count });
+        }
+
+        while self.instruction_count > count {
+            self.step_back();
+        }
+
+        Ok(())
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        if self.watchpoints.contains(&watchpoint) {
+            return false;
+        }
+
+        self.watchpoints.push(watchpoint);
+        true
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let length = self.watchpoints.len();
+        self.watchpoints.retain(|current| *current != watchpoint);
+
+        self.watchpoints.len() != length
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = Watchpoint> + '_ {
+        self.watchpoints.iter().copied()
+    }
+
+    // Reports every instruction that changes the register. IP changes when the instruction
+    // moves it anywhere but to the next instruction
+    pub fn watch_register(&mut self, register: Operand) -> Result<bool, RsmiscError> {
+        if self.register(register).is_none() {
+            return Err(RsmiscError::NotARegister { operand: register });
+        }
+        if self.watched_registers.contains(&register) {
+            return Ok(false);
+        }
+
+        self.watched_registers.push(register);
+        Ok(true)
+    }
+
+    pub fn unwatch_register(&mut self, register: Operand) -> bool {
+        let length = self.watched_registers.len();
+        self.watched_registers
+            .retain(|current| *current != register);
+
+        self.watched_registers.len() != length
+    }
+
+    pub fn watched_registers(&self) -> impl Iterator<Item = Operand> + '_ {
+        self.watched_registers.iter().copied()
+    }
+
+    // Watchpoints hit by the last instruction `execute_next` completed. `run` stops on them
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    fn watch_memory(&mut self, address: u16, length: usize, access: Access) {
+        let hit = self.watchpoints.iter().any(|watchpoint| {
+            watchpoint.access.includes(access)
+                && (0..length).any(|offset| {
+                    watchpoint.covers(((address as usize + offset) % MEMORY_SIZE) as u16)
+                })
+        });
+
+        if hit {
+            self.watch_hits.push(WatchHit::Memory { address, access });
+        }
+    }
+
+    // Values of the watched registers, IP as the address execution falls through to
+    fn register_snapshot(&self) -> Vec<u16> {
+        self.watched_registers
+            .iter()
+            .map(|register| match register {
+                Operand::IP => self.ip.wrapping_add(INSTRUCTION_SIZE as u16),
+                register => self.register(*register).unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    fn watch_registers(&mut self, before: &[u16]) {
+        for (register, old) in self.watched_registers.iter().zip(before) {
+            let new = self.register(*register).unwrap_or_default();
+
+            if new != *old {
+                self.watch_hits.push(WatchHit::Register {
+                    register: *register,
+                    old: *old,
+                    new,
+                });
+            }
+        }
+    }
+
+    pub fn halt(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
         }
 
         Ok(false)
@@ -105,7 +757,7 @@ impl Rsmisc {
 
     pub fn add(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
         }
 
         self.arithmetic_operation(instruction, ArithmeticOperation::Add)
@@ -113,7 +765,7 @@ impl Rsmisc {
 
     pub fn sub(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
         }
 
         self.arithmetic_operation(instruction, ArithmeticOperation::Sub)
@@ -121,7 +773,7 @@ impl Rsmisc {
 
     pub fn mul(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
         }
 
         self.arithmetic_operation(instruction, ArithmeticOperation::Mul)
@@ -129,7 +781,7 @@ impl Rsmisc {
 
     pub fn div(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
         }
 
         self.arithmetic_operation(instruction, ArithmeticOperation::Div)
@@ -140,8 +792,8 @@ impl Rsmisc {
         instruction: Instruction,
         operation: ArithmeticOperation,
     ) -> Result<bool, RsmiscError> {
-        let target = self.get_operand_value(instruction, OperandType::TARGET)?;
-        let source = self.get_operand_value(instruction, OperandType::SOURCE)?;
+        let target = self.read_operand(instruction, OperandType::TARGET)?;
+        let source = self.read_operand(instruction, OperandType::SOURCE)?;
 
         match operation {
             ArithmeticOperation::Add => {
@@ -156,6 +808,7 @@ impl Rsmisc {
                 self.stack.push(target.wrapping_mul(source));
                 Ok(true)
             }
+            ArithmeticOperation::Div if source == 0 => self.divide_by_zero(instruction),
             ArithmeticOperation::Div => {
                 self.stack.push(target.wrapping_div(source));
                 Ok(true)
@@ -163,15 +816,31 @@ impl Rsmisc {
         }
     }
 
+    fn divide_by_zero(&mut self, instruction: Instruction) -> Result<bool, RsmiscError> {
+        match (self.division_by_zero, self.trap_handler) {
+            (DivisionByZero::Trap, Some(handler)) => {
+                // Like CALL, but returning to the instruction after the DIV
+                self.call_stack.push(self.ip);
+                self.ip = handler.wrapping_sub(INSTRUCTION_SIZE as u16);
+                Ok(true)
+            }
+            _ => Err(RsmiscError::DivisionByZero {
+                ip: self.ip,
+                instruction,
+            }),
+        }
+    }
+
     pub fn mov(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
         }
 
-        let source = self.get_operand_value(instruction, OperandType::SOURCE)?;
-        let invalid_move_target = Err(RsmiscError {
-            code: -6,
-            message: format!("INVALID_MOVE_TARGET (at 0x{:x}", self.ip),
+        let source = self.read_operand(instruction, OperandType::SOURCE)?;
+        let invalid_move_target = Err(RsmiscError::InvalidMoveTarget {
+            ip: self.ip,
+            instruction,
+            operand: instruction.target,
         });
 
         match instruction.target {
@@ -202,18 +871,18 @@ impl Rsmisc {
 
     pub fn ld(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
         }
 
-        self.stack
-            .push(self.get_operand_value(instruction, OperandType::TARGET)?);
+        let value = self.read_operand(instruction, OperandType::TARGET)?;
+        self.stack.push(value);
 
         Ok(true)
     }
 
     pub fn uld(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
         }
 
         match self.stack.pop() {
@@ -238,29 +907,31 @@ impl Rsmisc {
                     self.ip = value;
                     Ok(true)
                 }
-                Operand::CT => Err(RsmiscError {
-                    code: -5,
-                    message: format!("INVALID_UNLOAD_TARGET (at 0x{:x}", self.ip),
+                Operand::CT => Err(RsmiscError::InvalidUnloadTarget {
+                    ip: self.ip,
+                    instruction,
+                    operand: instruction.target,
                 }),
                 Operand::MA => {
-                    self.store_16(instruction.target_imm, value);
+                    self.store_16(instruction.target_imm, value)?;
+                    self.watch_memory(instruction.target_imm, 2, Access::Write);
                     Ok(true)
                 }
             },
-            None => Err(RsmiscError {
-                code: -4,
-                message: format!("NO_ELEMENTS_IN_STACK (at 0x{:x}", self.ip),
+            None => Err(RsmiscError::NoElementsInStack {
+                ip: self.ip,
+                instruction,
             }),
         }
     }
 
     pub fn bz(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
         }
 
-        let target = self.get_operand_value(instruction, OperandType::TARGET)?;
-        let source = self.get_operand_value(instruction, OperandType::SOURCE)?;
+        let target = self.read_operand(instruction, OperandType::TARGET)?;
+        let source = self.read_operand(instruction, OperandType::SOURCE)?;
 
         if target == 0 {
             self.ip = source;
@@ -269,51 +940,320 @@ impl Rsmisc {
         Ok(true)
     }
 
-    pub fn swi(&self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
+    pub fn swi(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
+        }
+
+        let recording = self.recording();
+
+        if let Some(handler) = self.swi_handlers.get_mut(&instruction.target_imm) {
+            // Host handlers write memory directly, so compare it afterwards while recording
+            let previous = if recording {
+                Some(self.memory.clone())
+            } else {
+                None
+            };
+            let mut context = SwiContext {
+                ip: self.ip,
+                number: instruction.target_imm,
+                registers: &mut self.registers,
+                memory: &mut self.memory,
+                console: self.console.as_mut(),
+            };
+
+            let action = handler.handle(&mut context);
+
+            if let Some(previous) = previous {
+                let changed = previous
+                    .iter()
+                    .zip(&self.memory)
+                    .enumerate()
+                    .filter(|(_, (before, after))| before != after)
+                    .map(|(index, (before, _))| (index as u16, *before));
+
+                self.memory_log.extend(changed);
+            }
+
+            return match action {
+                Ok(SwiAction::Continue) => Ok(true),
+                Ok(SwiAction::Return(value)) => {
+                    self.registers[0] = value;
+                    Ok(true)
+                }
+                Ok(SwiAction::Halt) => Ok(false),
+                Err(message) => Err(RsmiscError::SoftwareInterruptFault {
+                    ip: self.ip,
+                    instruction,
+                    number: instruction.target_imm,
+                    message,
+                }),
+            };
         }
 
         match instruction.target_imm {
-            0x0 => self.print_character_swi(),
-            0x1 => self.print_number_swi(),
-            _ => Err(RsmiscError {
-                code: -3,
-                message: format!("UNIMPLEMENTED_SOFTWARE_INTERRUPT (at 0x{:x})", self.ip),
+            swi::PRINT_CHARACTER => self.print_character_swi(),
+            swi::PRINT_NUMBER => self.print_number_swi(),
+            swi::INSTALL_TRAP_HANDLER => self.install_trap_handler_swi(),
+            swi::READ_CHARACTER => self.read_character_swi(),
+            swi::READ_NUMBER => self.read_number_swi(),
+            swi::READ_LINE => self.read_line_swi(),
+            swi::PRINT_STRING => self.print_string_swi(),
+            swi::PRINT_STRING_LENGTH => self.print_string_length_swi(),
+            swi::PRINT_SIGNED => self.print_formatted_swi(true, 10),
+            swi::PRINT_UNSIGNED => self.print_formatted_swi(false, 10),
+            swi::PRINT_HEX => self.print_formatted_swi(false, 16),
+            swi::PRINT_BINARY => self.print_formatted_swi(false, 2),
+            swi::PRINT_RADIX => self.print_formatted_swi(false, self.registers[3] as u32),
+            number => Err(RsmiscError::UnimplementedSoftwareInterrupt {
+                ip: self.ip,
+                instruction,
+                number,
             }),
         }
     }
 
-    fn print_character_swi(&self) -> Result<bool, RsmiscError> {
+    fn print_character_swi(&mut self) -> Result<bool, RsmiscError> {
         if let Some(printable) = char::from_u32(self.registers[0] as u32) {
-            print!("{}", printable);
+            let mut buffer = [0; 4];
+            self.write_console(printable.encode_utf8(&mut buffer).as_bytes())?;
         }
 
         Ok(true)
     }
 
-    fn print_number_swi(&self) -> Result<bool, RsmiscError> {
-        print!("{}", self.registers[0]);
+    fn print_number_swi(&mut self) -> Result<bool, RsmiscError> {
+        self.write_console(self.registers[0].to_string().as_bytes())?;
+
+        Ok(true)
+    }
+
+    fn print_formatted_swi(&mut self, signed: bool, radix: u32) -> Result<bool, RsmiscError> {
+        let text = swi::format_number(
+            self.registers[0],
+            signed,
+            radix,
+            self.registers[1] as usize,
+            self.registers[2] != 0,
+        )
+        .ok_or(RsmiscError::InvalidRadix {
+            ip: self.ip,
+            radix: radix as u16,
+        })?;
+
+        self.write_console(text.as_bytes())?;
 
         Ok(true)
     }
 
+    // Prints the NUL-terminated string at the address in R1
+    fn print_string_swi(&mut self) -> Result<bool, RsmiscError> {
+        let address = self.registers[0];
+        let length = self.memory[address as usize..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(RsmiscError::StringOutOfBounds {
+                ip: self.ip,
+                address,
+            })?;
+
+        self.print_string(address, length)
+    }
+
+    // Prints R2 bytes starting at the address in R1
+    fn print_string_length_swi(&mut self) -> Result<bool, RsmiscError> {
+        self.print_string(self.registers[0], self.registers[1] as usize)
+    }
+
+    fn print_string(&mut self, address: u16, length: usize) -> Result<bool, RsmiscError> {
+        let start = address as usize;
+
+        if start + length > MEMORY_SIZE {
+            return Err(RsmiscError::StringOutOfBounds {
+                ip: self.ip,
+                address,
+            });
+        }
+
+        self.watch_memory(address, length, Access::Read);
+
+        let text = String::from_utf8_lossy(&self.memory[start..start + length]).into_owned();
+        self.write_console(text.as_bytes())?;
+
+        Ok(true)
+    }
+
+    fn write_console(&mut self, bytes: &[u8]) -> Result<(), RsmiscError> {
+        let ip = self.ip;
+
+        self.console
+            .write(bytes)
+            .map_err(|error| RsmiscError::io(ip, error))
+    }
+
+    // Installs the division by zero handler at the address in R1
+    fn install_trap_handler_swi(&mut self) -> Result<bool, RsmiscError> {
+        self.trap_handler = Some(self.registers[0]);
+
+        Ok(true)
+    }
+
+    // Input interrupts set R4 to 1 when something was read and to 0 at end of input
+
+    // Reads one UTF-8 character into R1 (0xFFFD if it does not fit in 16 bits)
+    fn read_character_swi(&mut self) -> Result<bool, RsmiscError> {
+        let first = match self.read_console_byte()? {
+            Some(byte) => byte,
+            None => return self.end_of_input(),
+        };
+        let length = match first {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        let mut bytes = vec![first];
+
+        while bytes.len() < length {
+            match self.read_console_byte()? {
+                Some(byte) => bytes.push(byte),
+                None => break,
+            }
+        }
+
+        let character = std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|text| text.chars().next())
+            .filter(|character| (*character as u32) <= 0xffff)
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+
+        self.registers[0] = character as u16;
+        self.registers[3] = 1;
+
+        Ok(true)
+    }
+
+    // Reads a decimal number into R1, skipping leading whitespace. A leading '-' stores the
+    // two's complement. The byte ending the number is consumed
+    fn read_number_swi(&mut self) -> Result<bool, RsmiscError> {
+        let mut text = String::new();
+
+        let mut next = self.read_console_byte()?;
+        while matches!(next, Some(byte) if byte.is_ascii_whitespace()) {
+            next = self.read_console_byte()?;
+        }
+
+        while let Some(byte) = next {
+            if byte.is_ascii_digit() || (byte == b'-' && text.is_empty()) {
+                text.push(byte as char);
+                next = self.read_console_byte()?;
+            } else if byte.is_ascii_whitespace() {
+                break;
+            } else {
+                text.push(byte as char);
+                return Err(RsmiscError::InvalidNumber { ip: self.ip, text });
+            }
+        }
+
+        if text.is_empty() {
+            return self.end_of_input();
+        }
+
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.as_str()),
+        };
+        let value = match digits.parse::<u16>() {
+            Ok(value) if !negative => value,
+            Ok(value) if value <= 0x8000 => value.wrapping_neg(),
+            _ => return Err(RsmiscError::InvalidNumber { ip: self.ip, text }),
+        };
+
+        self.registers[0] = value;
+        self.registers[3] = 1;
+
+        Ok(true)
+    }
+
+    // Reads a line into memory at the address in R1, storing at most R2 bytes followed by a NUL
+    // (so the buffer needs R2 + 1 bytes). The line ending is not stored and the rest of a longer
+    // line is discarded. R2 is set to the number of bytes stored
+    fn read_line_swi(&mut self) -> Result<bool, RsmiscError> {
+        let address = self.registers[0];
+        let capacity = self.registers[1] as usize;
+
+        // Check the whole buffer before consuming any input
+        self.memory_index(address, capacity, capacity + 1)?;
+
+        let mut length = 0;
+        let mut read_any = false;
+
+        while let Some(byte) = self.read_console_byte()? {
+            read_any = true;
+
+            match byte {
+                b'\n' => break,
+                b'\r' => {}
+                _ if length < capacity => {
+                    let index = self.memory_index(address, length, capacity + 1)?;
+                    self.write_byte(index, byte);
+                    length += 1;
+                }
+                _ => {}
+            }
+        }
+
+        let index = self.memory_index(address, length, capacity + 1)?;
+        self.write_byte(index, 0);
+        self.registers[1] = length as u16;
+
+        self.watch_memory(address, length + 1, Access::Write);
+
+        if !read_any {
+            return self.end_of_input();
+        }
+
+        self.registers[3] = 1;
+
+        Ok(true)
+    }
+
+    fn end_of_input(&mut self) -> Result<bool, RsmiscError> {
+        self.registers[3] = 0;
+
+        Ok(true)
+    }
+
+    fn read_console_byte(&mut self) -> Result<Option<u8>, RsmiscError> {
+        let mut buffer = [0; 1];
+
+        loop {
+            match self.console.read(&mut buffer) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(buffer[0])),
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(RsmiscError::io(self.ip, error)),
+            }
+        }
+    }
+
     pub fn call(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
         }
 
-        let target = self.get_operand_value(instruction, OperandType::TARGET)?;
+        let target = self.read_operand(instruction, OperandType::TARGET)?;
 
-        self.call_stack.push(self.ip + (INSTRUCTION_SIZE as u16));
-        self.ip = target - (INSTRUCTION_SIZE as u16);
+        self.call_stack
+            .push(self.ip.wrapping_add(INSTRUCTION_SIZE as u16));
+        self.ip = target.wrapping_sub(INSTRUCTION_SIZE as u16);
 
         Ok(true)
     }
 
     pub fn ret(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
         }
 
         match self.call_stack.pop() {
@@ -321,16 +1261,16 @@ impl Rsmisc {
                 self.ip = value;
                 Ok(true)
             }
-            None => Err(RsmiscError {
-                code: -2,
-                message: format!("CALL_STACK_EMPTY (at 0x{:x})", self.ip),
+            None => Err(RsmiscError::CallStackEmpty {
+                ip: self.ip,
+                instruction,
             }),
         }
     }
 
-    pub fn nop(&self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
+    pub fn nop(&mut self, instruction: Instruction, print: bool) -> Result<bool, RsmiscError> {
         if print {
-            self.print_instruction(&instruction);
+            self.print_instruction(&instruction)?;
         }
 
         Ok(true)
@@ -351,18 +1291,56 @@ impl Rsmisc {
         };
 
         match operand {
-            Operand::R1 => Ok(self.registers[0] as u16),
-            Operand::R2 => Ok(self.registers[1] as u16),
-            Operand::R3 => Ok(self.registers[2] as u16),
-            Operand::R4 => Ok(self.registers[3] as u16),
+            Operand::R1 => Ok(self.registers[0]),
+            Operand::R2 => Ok(self.registers[1]),
+            Operand::R3 => Ok(self.registers[2]),
+            Operand::R4 => Ok(self.registers[3]),
             Operand::IP => Ok(self.ip),
             Operand::CT => Ok(immediate),
             Operand::MA => self.load_16(immediate),
         }
     }
 
-    pub fn print_instruction(&self, instruction: &Instruction) {
-        println!("0x{:x}:\t {}", self.ip, instruction);
+    // `get_operand_value` for the executing instruction, recording watched memory reads
+    fn read_operand(
+        &mut self,
+        instruction: Instruction,
+        operand_type: OperandType,
+    ) -> Result<u16, RsmiscError> {
+        let value = self.get_operand_value(instruction, operand_type)?;
+
+        let (operand, address) = match operand_type {
+            OperandType::TARGET => (instruction.target, instruction.target_imm),
+            OperandType::SOURCE => (instruction.source, instruction.source_imm),
+        };
+        if operand == Operand::MA {
+            self.watch_memory(address, 2, Access::Read);
+        }
+
+        Ok(value)
+    }
+
+    pub fn print_instruction(&mut self, instruction: &Instruction) -> Result<(), RsmiscError> {
+        self.write_console(format!("0x{:x}:\t {}\n", self.ip, instruction).as_bytes())
+    }
+
+    pub fn flush_console(&mut self) -> Result<(), RsmiscError> {
+        let ip = self.ip;
+
+        self.console
+            .flush()
+            .map_err(|error| RsmiscError::io(ip, error))
+    }
+}
+
+impl Debug for Rsmisc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Rsmisc")
+            .field("ip", &self.ip)
+            .field("registers", &self.registers)
+            .field("stack", &self.stack)
+            .field("call_stack", &self.call_stack)
+            .finish_non_exhaustive()
     }
 }
 
@@ -371,13 +1349,13 @@ impl Display for Rsmisc {
         let call_stack = if let Some(last) = self.call_stack.last() {
             format!("CS: 0x{:x}\tCSP: 0x{:x}", last, self.call_stack.len())
         } else {
-            format!("CS: -\t\tCSP: 0x0")
+            "CS: -\t\tCSP: 0x0".to_string()
         };
 
         let stack = if let Some(last) = self.stack.last() {
             format!("S: 0x{:x}\tSP: 0x{:x}", last, self.stack.len())
         } else {
-            format!("S: -\t\tSP: 0x0")
+            "S: -\t\tSP: 0x0".to_string()
         };
 
         let r1_r2 = format!(
@@ -399,9 +1377,3 @@ impl Display for Rsmisc {
         )
     }
 }
-
-#[derive(Debug, Clone)]
-pub struct RsmiscError {
-    pub code: i32,
-    pub message: String,
-}
@@ -0,0 +1,729 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::process;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use rsmisc::asm::parse_register;
+use rsmisc::chrome_trace::ChromeTrace;
+use rsmisc::dap;
+use rsmisc::gdb;
+use rsmisc::history::HISTORY_LIMIT;
+use rsmisc::instruction::{Instruction, Opcode};
+use rsmisc::outcome::Outcome;
+use rsmisc::profile::Profile;
+use rsmisc::trace::{JsonLinesTracer, TraceRecord, Tracer};
+use rsmisc::watch::{Access, WatchHit, Watchpoint};
+use rsmisc::{Rsmisc, INSTRUCTION_SIZE};
+
+const HELP: &str = "\
+step [n]            execute n instructions (s)
+next [n]            like step, but runs CALLs to completion (n)
+continue            run until a breakpoint, HALT or fault (c)
+reverse-step [n]    undo n instructions (rs)
+reverse-continue    undo instructions until a breakpoint (rc)
+goto <count>        undo instructions until the instruction count is reached
+history [n]         show the instruction count, or record the last n instructions
+break <location>    set a breakpoint at an address or label (b)
+delete <location>   remove a breakpoint (d)
+info breakpoints    list breakpoints
+watch <location|register> [n]
+                    stop after an instruction writes n bytes (default 2) or
+                    changes a register
+rwatch <location> [n]
+                    stop after an instruction reads memory
+awatch <location> [n]
+                    stop after an instruction reads or writes memory
+unwatch <location|register>
+                    remove the watchpoints starting at an address, or a register
+info watchpoints    list watchpoints
+regs                show the machine state (info registers)
+stack               show the data stack and the call stack
+x <location> [n]    dump n bytes of memory (default 0x10)
+set <register> <v>  set R1 to R4 or IP
+write <location> <byte>...
+                    write bytes to memory
+disas [location] [n]
+                    disassemble n instructions around an address (default IP)
+save <file>         write a snapshot of the machine state
+load <file>         restore a snapshot
+trace <file|off>    write a JSON line for every executed instruction to a file
+timeline <file|off> record calls as a Chrome trace, written to the file when
+                    turned off or on quit
+profile [on|off]    count executed instructions per address, opcode and function
+profile report      show the counts, most expensive first
+profile stacks <file>
+                    write the counts per call stack for flamegraph tools
+help                show this message
+quit                exit (q)
+
+Numbers are hexadecimal, the same as in the assembler";
+
+struct Debugger {
+    vm: Rsmisc,
+    labels: BTreeMap<String, u16>,
+    trace: Option<Arc<Mutex<JsonLinesTracer<io::LineWriter<std::fs::File>>>>>,
+    // Calls being recorded, and the file they are written to
+    timeline: Option<(ChromeTrace, String)>,
+    profile: Option<Profile>,
+}
+
+impl Debugger {
+    fn execute(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let arguments = words.get(1..).unwrap_or_default();
+
+        let result = match words.first().copied().unwrap_or_default() {
+            "" => Ok(()),
+            "step" | "s" => self.step(arguments, false),
+            "next" | "n" => self.step(arguments, true),
+            "continue" | "c" => self.resume(),
+            "reverse-step" | "rs" => self.reverse_step(arguments),
+            "reverse-continue" | "rc" => self.reverse_resume(),
+            "goto" => self.goto(arguments),
+            "history" => self.history(arguments),
+            "break" | "b" => self.set_breakpoint(arguments),
+            "delete" | "d" => self.delete_breakpoint(arguments),
+            "watch" => self.watch(arguments, Access::Write),
+            "rwatch" => self.watch(arguments, Access::Read),
+            "awatch" => self.watch(arguments, Access::ReadWrite),
+            "unwatch" => self.unwatch(arguments),
+            "info" if arguments.first() == Some(&"breakpoints") => self.list_breakpoints(),
+            "info" if arguments.first() == Some(&"watchpoints") => self.list_watchpoints(),
+            "info" if arguments.first() == Some(&"registers") => self.show_state(),
+            "regs" => self.show_state(),
+            "stack" => self.show_stacks(),
+            "x" => self.dump(arguments),
+            "set" => self.set_register(arguments),
+            "write" => self.write(arguments),
+            "disas" => self.disassemble(arguments),
+            "save" => self.save_snapshot(arguments),
+            "load" => self.load_snapshot(arguments),
+            "trace" => self.trace(arguments),
+            "timeline" => self.timeline(arguments),
+            "profile" => self.profile(arguments),
+            "help" | "h" => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            "quit" | "q" => return false,
+            command => Err(format!("unknown command '{}', try 'help'", command)),
+        };
+
+        if let Err(message) = result {
+            println!("{}", message);
+        }
+
+        true
+    }
+
+    fn step(&mut self, arguments: &[&str], over_calls: bool) -> Result<(), String> {
+        let count = match arguments.first() {
+            Some(count) => parse_number(count)?,
+            None => 1,
+        };
+
+        for _ in 0..count {
+            let depth = self.vm.call_stack().len();
+            let is_call = matches!(self.current_instruction(), Ok(i) if i.op_code == Opcode::CALL);
+
+            if let Some(outcome) = self.step_once() {
+                return self.report(outcome);
+            }
+
+            // Run the called subroutine until it returns, unless something stops it first
+            while over_calls && is_call && self.vm.call_stack().len() > depth {
+                if self.vm.breakpoints().any(|address| address == self.vm.ip()) {
+                    return self.report(Outcome::Breakpoint(self.vm.ip()));
+                }
+                if let Some(outcome) = self.step_once() {
+                    return self.report(outcome);
+                }
+            }
+        }
+
+        self.show_current()
+    }
+
+    // Returns the outcome if the machine stopped for good
+    fn step_once(&mut self) -> Option<Outcome> {
+        match self.vm.run(1).outcome {
+            Outcome::StepLimitReached | Outcome::Breakpoint(_) => None,
+            outcome => Some(outcome),
+        }
+    }
+
+    fn resume(&mut self) -> Result<(), String> {
+        let result = self.vm.run(u64::MAX);
+
+        println!("0x{:x} instructions executed", result.steps);
+        self.report(result.outcome)
+    }
+
+    fn report(&mut self, outcome: Outcome) -> Result<(), String> {
+        let _ = self.vm.flush_console();
+
+        match outcome {
+            Outcome::Halted => println!("Halted"),
+            Outcome::StepLimitReached => {}
+            Outcome::Breakpoint(address) => {
+                println!("Breakpoint at {}", self.describe(address))
+            }
+            Outcome::Watchpoint { ip, hits } => {
+                for hit in hits {
+                    match hit {
+                        WatchHit::Memory { address, access } => println!(
+                            "Watchpoint: {:?} of 0x{:x} by {}",
+                            access,
+                            address,
+                            self.describe(ip)
+                        ),
+                        WatchHit::Register { register, old, new } => println!(
+                            "Watchpoint: {:?} 0x{:x} -> 0x{:x} by {}",
+                            register,
+                            old,
+                            new,
+                            self.describe(ip)
+                        ),
+                    }
+                }
+            }
+            Outcome::Fault(error) => println!("Fault: {}", error),
+            Outcome::StartOfHistory => println!("No more reverse execution history"),
+        }
+
+        self.show_current()
+    }
+
+    fn reverse_step(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let count = match arguments.first() {
+            Some(count) => parse_number(count)?,
+            None => 1,
+        };
+
+        match self.vm.run_back(count as u64).outcome {
+            Outcome::StepLimitReached | Outcome::Breakpoint(_) => self.show_current(),
+            outcome => self.report(outcome),
+        }
+    }
+
+    fn reverse_resume(&mut self) -> Result<(), String> {
+        let result = self.vm.run_back(u64::MAX);
+
+        println!("0x{:x} instructions undone", result.steps);
+        self.report(result.outcome)
+    }
+
+    fn goto(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let argument = arguments
+            .first()
+            .ok_or_else(|| "Usage: goto <count>".to_string())?;
+        let count = u64::from_str_radix(argument.trim_start_matches("0x"), 16)
+            .map_err(|_| format!("Invalid number '{}'", argument))?;
+
+        self.vm
+            .goto_instruction(count)
+            .map_err(|error| error.to_string())?;
+
+        self.show_current()
+    }
+
+    fn history(&mut self, arguments: &[&str]) -> Result<(), String> {
+        if let Some(limit) = arguments.first() {
+            self.vm.set_history_limit(parse_number(limit)? as usize);
+        }
+
+        let recorded = self.vm.history().count() as u64;
+
+        println!(
+            "0x{:x} instructions executed, 0x{:x} to 0x{:x} reachable (limit 0x{:x})",
+            self.vm.instruction_count(),
+            self.vm.instruction_count() - recorded,
+            self.vm.instruction_count(),
+            self.vm.history_limit()
+        );
+
+        Ok(())
+    }
+
+    fn set_breakpoint(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let address = self.location(arguments.first())?;
+
+        if self.vm.add_breakpoint(address) {
+            println!("Breakpoint at {}", self.describe(address));
+        }
+
+        Ok(())
+    }
+
+    fn delete_breakpoint(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let address = self.location(arguments.first())?;
+
+        if self.vm.remove_breakpoint(address) {
+            Ok(())
+        } else {
+            Err(format!("No breakpoint at {}", self.describe(address)))
+        }
+    }
+
+    fn list_breakpoints(&mut self) -> Result<(), String> {
+        for address in self.vm.breakpoints() {
+            println!("{}", self.describe(address));
+        }
+
+        Ok(())
+    }
+
+    fn watch(&mut self, arguments: &[&str], access: Access) -> Result<(), String> {
+        if let Some(register) = arguments.first().and_then(|name| parse_register(name)) {
+            if access != Access::Write {
+                return Err("Registers can only be watched for changes".to_string());
+            }
+
+            return self
+                .vm
+                .watch_register(register)
+                .map(|_| ())
+                .map_err(|error| error.to_string());
+        }
+
+        let start = self.location(arguments.first())?;
+        let length = match arguments.get(1) {
+            Some(length) => parse_number(length)?.max(1),
+            None => 2,
+        };
+        let end = start
+            .checked_add(length - 1)
+            .ok_or_else(|| "Watched range runs past the end of memory".to_string())?;
+
+        self.vm.add_watchpoint(Watchpoint::new(start, end, access));
+
+        Ok(())
+    }
+
+    fn unwatch(&mut self, arguments: &[&str]) -> Result<(), String> {
+        if let Some(register) = arguments.first().and_then(|name| parse_register(name)) {
+            if !self.vm.unwatch_register(register) {
+                return Err(format!("{:?} is not watched", register));
+            }
+
+            return Ok(());
+        }
+
+        let start = self.location(arguments.first())?;
+        let watchpoints: Vec<Watchpoint> = self
+            .vm
+            .watchpoints()
+            .filter(|watchpoint| watchpoint.start == start)
+            .collect();
+
+        if watchpoints.is_empty() {
+            return Err(format!("No watchpoint at {}", self.describe(start)));
+        }
+
+        for watchpoint in watchpoints {
+            self.vm.remove_watchpoint(watchpoint);
+        }
+
+        Ok(())
+    }
+
+    fn list_watchpoints(&mut self) -> Result<(), String> {
+        for watchpoint in self.vm.watchpoints() {
+            println!(
+                "{:?} {}..0x{:x}",
+                watchpoint.access,
+                self.describe(watchpoint.start),
+                watchpoint.end
+            );
+        }
+
+        for register in self.vm.watched_registers() {
+            println!("{:?}", register);
+        }
+
+        Ok(())
+    }
+
+    fn show_state(&mut self) -> Result<(), String> {
+        println!("{}", self.vm);
+        Ok(())
+    }
+
+    fn show_stacks(&mut self) -> Result<(), String> {
+        println!("Data stack (top first):");
+        for value in self.vm.stack().iter().rev() {
+            println!("  0x{:x}", value);
+        }
+
+        println!("Call stack (top first):");
+        for address in self.vm.call_stack().iter().rev() {
+            println!("  {}", self.describe(*address));
+        }
+
+        Ok(())
+    }
+
+    fn dump(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let address = self.location(arguments.first())?;
+        let length = match arguments.get(1) {
+            Some(length) => parse_number(length)? as usize,
+            None => 0x10,
+        };
+        let bytes = self
+            .vm
+            .read_memory(address, length)
+            .map_err(|error| error.to_string())?;
+
+        for (index, row) in bytes.chunks(0x10).enumerate() {
+            let hex: Vec<String> = row.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let text: String = row
+                .iter()
+                .map(|byte| match *byte {
+                    0x20..=0x7e => *byte as char,
+                    _ => '.',
+                })
+                .collect();
+
+            println!(
+                "0x{:04x}:  {:<47}  {}",
+                address as usize + index * 0x10,
+                hex.join(" "),
+                text
+            );
+        }
+
+        Ok(())
+    }
+
+    fn set_register(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let register = arguments
+            .first()
+            .and_then(|name| parse_register(name))
+            .ok_or_else(|| "Usage: set <R1|R2|R3|R4|IP> <value>".to_string())?;
+        let value = self.location(arguments.get(1))?;
+
+        self.vm
+            .set_register(register, value)
+            .map_err(|error| error.to_string())
+    }
+
+    fn write(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let address = self.location(arguments.first())?;
+        let bytes = arguments
+            .get(1..)
+            .unwrap_or_default()
+            .iter()
+            .map(|byte| match parse_number(byte)? {
+                byte if byte <= 0xff => Ok(byte as u8),
+                byte => Err(format!("Byte out of range: {:x}", byte)),
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+
+        self.vm
+            .write_memory(address, &bytes)
+            .map_err(|error| error.to_string())
+    }
+
+    fn disassemble(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let center = match arguments.first() {
+            Some(_) => self.location(arguments.first())? as usize,
+            None => self.vm.ip() as usize,
+        };
+        let count = match arguments.get(1) {
+            Some(count) => parse_number(count)? as usize,
+            None => 0x8,
+        };
+        // Start a few instructions before the address when there is room for them
+        let start = center - (count / 2).min(center / INSTRUCTION_SIZE) * INSTRUCTION_SIZE;
+
+        for index in 0..count {
+            let address = start + index * INSTRUCTION_SIZE;
+
+            if address + INSTRUCTION_SIZE > rsmisc::MEMORY_SIZE {
+                break;
+            }
+
+            self.show_instruction(address as u16)?;
+        }
+
+        Ok(())
+    }
+
+    fn save_snapshot(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let path = arguments
+            .first()
+            .ok_or_else(|| "Usage: save <file>".to_string())?;
+        let file = std::fs::File::create(path).map_err(|error| error.to_string())?;
+
+        self.vm
+            .save_snapshot(io::BufWriter::new(file))
+            .map_err(|error| error.to_string())
+    }
+
+    fn load_snapshot(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let path = arguments
+            .first()
+            .ok_or_else(|| "Usage: load <file>".to_string())?;
+        let file = std::fs::File::open(path).map_err(|error| error.to_string())?;
+
+        self.vm
+            .load_snapshot(io::BufReader::new(file))
+            .map_err(|error| error.to_string())?;
+
+        self.show_current()
+    }
+
+    fn trace(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let path = arguments
+            .first()
+            .ok_or_else(|| "Usage: trace <file|off>".to_string())?;
+
+        // Only one trace at a time, dropping the previous one closes its file
+        self.trace = None;
+
+        if *path != "off" {
+            let file = std::fs::File::create(path).map_err(|error| error.to_string())?;
+            self.trace = Some(Arc::new(Mutex::new(JsonLinesTracer::new(
+                io::LineWriter::new(file),
+            ))));
+        }
+
+        self.attach_tracers();
+        Ok(())
+    }
+
+    fn timeline(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let path = arguments
+            .first()
+            .ok_or_else(|| "Usage: timeline <file|off>".to_string())?;
+
+        self.stop_timeline()?;
+
+        if *path != "off" {
+            let timeline = ChromeTrace::with_symbols(&self.labels);
+            self.timeline = Some((timeline, path.to_string()));
+        }
+
+        self.attach_tracers();
+        Ok(())
+    }
+
+    // Writes the timeline being recorded, if any, to its file
+    fn stop_timeline(&mut self) -> Result<(), String> {
+        if let Some((timeline, path)) = self.timeline.take() {
+            let file = std::fs::File::create(&path).map_err(|error| error.to_string())?;
+            timeline
+                .write(io::BufWriter::new(file))
+                .map_err(|error| format!("{}: {}", path, error))?;
+        }
+
+        Ok(())
+    }
+
+    fn profile(&mut self, arguments: &[&str]) -> Result<(), String> {
+        match arguments {
+            [] | ["on"] => {
+                self.profile = Some(Profile::with_symbols(&self.labels));
+                self.attach_tracers();
+            }
+            ["off"] => {
+                self.profile = None;
+                self.attach_tracers();
+            }
+            ["report"] => print!("{}", self.active_profile()?.report()),
+            ["stacks", path] => {
+                let stacks = self.active_profile()?.collapsed_stacks();
+                std::fs::write(path, stacks).map_err(|error| error.to_string())?;
+            }
+            _ => return Err("Usage: profile [on|off|report|stacks <file>]".to_string()),
+        }
+
+        Ok(())
+    }
+
+    fn active_profile(&self) -> Result<&Profile, String> {
+        self.profile
+            .as_ref()
+            .ok_or_else(|| "not profiling, start with 'profile'".to_string())
+    }
+
+    fn attach_tracers(&mut self) {
+        self.vm.clear_tracers();
+
+        if let Some(trace) = &self.trace {
+            let trace = trace.clone();
+            // Ignores poisoning, the same as the library's locks
+            self.vm.add_tracer(move |record: &TraceRecord| {
+                trace
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .trace(record)
+            });
+        }
+        if let Some((timeline, _)) = &self.timeline {
+            self.vm.add_tracer(timeline.clone());
+        }
+        if let Some(profile) = &self.profile {
+            self.vm.add_tracer(profile.clone());
+        }
+    }
+
+    fn show_current(&mut self) -> Result<(), String> {
+        self.show_instruction(self.vm.ip())
+    }
+
+    fn show_instruction(&self, address: u16) -> Result<(), String> {
+        if let Some((label, _)) = self.labels.iter().find(|(_, value)| **value == address) {
+            println!("{}:", label);
+        }
+
+        let marker = if address == self.vm.ip() { "=>" } else { "  " };
+        let breakpoint = if self.vm.breakpoints().any(|value| value == address) {
+            "*"
+        } else {
+            " "
+        };
+
+        match self.instruction_at(address) {
+            Ok(instruction) => println!(
+                "{}{} 0x{:x}:\t {}",
+                marker, breakpoint, address, instruction
+            ),
+            Err(message) => println!("{}{} 0x{:x}:\t {}", marker, breakpoint, address, message),
+        }
+
+        Ok(())
+    }
+
+    fn current_instruction(&self) -> Result<Instruction, String> {
+        self.instruction_at(self.vm.ip())
+    }
+
+    fn instruction_at(&self, address: u16) -> Result<Instruction, String> {
+        self.vm
+            .load_48(address)
+            .map(Instruction::from)
+            .map_err(|error| error.to_string())
+    }
+
+    fn location(&self, argument: Option<&&str>) -> Result<u16, String> {
+        let argument = argument.ok_or_else(|| "Missing address or label".to_string())?;
+
+        match self.labels.get(*argument) {
+            Some(address) => Ok(*address),
+            None => parse_number(argument),
+        }
+    }
+
+    fn describe(&self, address: u16) -> String {
+        match self.labels.iter().find(|(_, value)| **value == address) {
+            Some((label, _)) => format!("0x{:x} <{}>", address, label),
+            None => format!("0x{:x}", address),
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix('#'))
+        .unwrap_or(text);
+
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid number '{}'", text))
+}
+
+// Serves GDB on a TCP address (host:port) or else a Unix socket path
+fn serve_gdb(vm: &mut Rsmisc, address: &str) -> io::Result<()> {
+    eprintln!("Waiting for GDB on {}", address);
+
+    #[cfg(unix)]
+    if !address.contains(':') {
+        return gdb::listen_unix(vm, address);
+    }
+
+    gdb::listen_tcp(vm, address)
+}
+
+fn main() {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    let (gdb_address, path) = match arguments.as_slice() {
+        // The client launches the program, stdin and stdout carry the protocol
+        [flag] if flag == "--dap" => {
+            let stdin = io::stdin();
+
+            if let Err(error) = dap::serve(stdin.lock(), io::stdout()) {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+
+            return;
+        }
+        [path] => (None, path.clone()),
+        [flag, address, path] if flag == "--gdb" => (Some(address.clone()), path.clone()),
+        _ => {
+            eprintln!("Usage: rsmisc-dbg [--gdb <host:port | socket>] <image | source.asm>");
+            eprintln!("       rsmisc-dbg --dap");
+            process::exit(2);
+        }
+    };
+    let assembly = dap::load(&path).unwrap_or_else(|message| {
+        eprintln!("{}: {}", path, message);
+        process::exit(1);
+    });
+    let mut vm = Rsmisc::new(&assembly.image).unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+
+    vm.set_history_limit(HISTORY_LIMIT);
+
+    if let Some(address) = gdb_address {
+        if let Err(error) = serve_gdb(&mut vm, &address) {
+            eprintln!("{}: {}", address, error);
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    let mut debugger = Debugger {
+        vm,
+        labels: assembly.labels,
+        trace: None,
+        timeline: None,
+        profile: None,
+    };
+    let mut last = String::new();
+
+    let _ = debugger.show_current();
+
+    loop {
+        print!("(rsmisc) ");
+        let _ = io::stdout().flush();
+
+        // Stdin is only locked while reading a command, the guest reads from it too
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let line = line.trim_end_matches(&['\r', '\n'][..]).to_string();
+
+        // An empty line repeats the previous command, like GDB
+        let line = if line.trim().is_empty() {
+            last.clone()
+        } else {
+            line
+        };
+
+        if !debugger.execute(&line) {
+            break;
+        }
+
+        last = line;
+    }
+
+    if let Err(message) = debugger.stop_timeline() {
+        eprintln!("{}", message);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::division_by_zero::DivisionByZero;
+use crate::gas::CostTable;
+use crate::instruction::Opcode;
+use crate::memory_access::MemoryAccess;
+use crate::{Rsmisc, RsmiscError, MEMORY_SIZE};
+
+// Snapshot layout, every number is big-endian:
+//
+//     magic     8 bytes, "RSMISC" 0x00 0x53
+//     version   u16
+//     records   tag u16, length u32, `length` bytes of data
+//     checksum  u32, CRC-32 of everything before it
+//
+// Memory, IP and registers are required. Other records fall back to the state of a new machine
+// when missing and unknown tags are skipped, so later versions only add records and snapshots of
+// any version up to `VERSION` load. The console, interrupt handlers, breakpoints, watchpoints
+// and history belong to the host and are not saved
+
+pub const MAGIC: [u8; 0x8] = *b"RSMISC\0S";
+pub const VERSION: u16 = 0x1;
+
+const MEMORY: u16 = 0x1;
+const IP: u16 = 0x2;
+const REGISTERS: u16 = 0x3;
+const STACK: u16 = 0x4;
+const CALL_STACK: u16 = 0x5;
+// Absent when no handler is installed
+const TRAP_HANDLER: u16 = 0x6;
+// Strict decoding, memory access and division by zero modes, one byte each
+const OPTIONS: u16 = 0x7;
+// Absent when metering is disabled
+const GAS: u16 = 0x8;
+// Opcodes in `Opcode::ALL` order, then register, constant and memory operands
+const COST_TABLE: u16 = 0x9;
+const INSTRUCTION_COUNT: u16 = 0xa;
+
+pub fn encode(vm: &Rsmisc) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_be_bytes());
+
+    record(&mut bytes, MEMORY, &vm.memory);
+    record(&mut bytes, IP, &vm.ip.to_be_bytes());
+    record(&mut bytes, REGISTERS, &words(&vm.registers));
+    record(&mut bytes, STACK, &words(&vm.stack));
+    record(&mut bytes, CALL_STACK, &words(&vm.call_stack));
+
+    if let Some(handler) = vm.trap_handler {
+        record(&mut bytes, TRAP_HANDLER, &handler.to_be_bytes());
+    }
+
+    let options = [
+        vm.strict_decoding as u8,
+        match vm.memory_access {
+            MemoryAccess::Fault => 0,
+            MemoryAccess::Wrap => 1,
+        },
+        match vm.division_by_zero {
+            DivisionByZero::Fault => 0,
+            DivisionByZero::Trap => 1,
+        },
+    ];
+    record(&mut bytes, OPTIONS, &options);
+
+    if let Some(gas) = vm.gas {
+        record(&mut bytes, GAS, &gas.to_be_bytes());
+    }
+
+    let costs: Vec<u8> = Opcode::ALL
+        .iter()
+        .map(|op_code| vm.cost_table.opcode(*op_code))
+        .chain(vec![
+            vm.cost_table.register,
+            vm.cost_table.constant,
+            vm.cost_table.memory,
+        ])
+        .flat_map(|cost| cost.to_be_bytes().to_vec())
+        .collect();
+    record(&mut bytes, COST_TABLE, &costs);
+    record(
+        &mut bytes,
+        INSTRUCTION_COUNT,
+        &vm.instruction_count.to_be_bytes(),
+    );
+
+    let checksum = crc32(&bytes);
+    bytes.extend_from_slice(&checksum.to_be_bytes());
+
+    bytes
+}
+
+// Restores the state in `bytes` into `vm`, which is left untouched if the snapshot is invalid
+pub fn decode(vm: &mut Rsmisc, bytes: &[u8]) -> Result<(), RsmiscError> {
+    if bytes.len() < MAGIC.len() + 0x6 || bytes[..MAGIC.len()] != MAGIC {
+        return Err(invalid("not a snapshot"));
+    }
+
+    let (content, checksum) = bytes.split_at(bytes.len() - 0x4);
+    if crc32(content).to_be_bytes() != checksum {
+        return Err(invalid("checksum mismatch"));
+    }
+
+    let version = u16::from_be_bytes([content[0x8], content[0x9]]);
+    if version > VERSION {
+        return Err(invalid(&format!("unsupported version {}", version)));
+    }
+
+    // A new machine provides the defaults for missing records
+    let mut state = Rsmisc::new(&[])?;
+    let mut found = Vec::new();
+    let mut rest = &content[MAGIC.len() + 0x2..];
+
+    while !rest.is_empty() {
+        if rest.len() < 0x6 {
+            return Err(invalid("truncated record header"));
+        }
+
+        let tag = u16::from_be_bytes([rest[0], rest[1]]);
+        let length = u32::from_be_bytes([rest[2], rest[3], rest[4], rest[5]]) as usize;
+        let data = rest
+            .get(0x6..0x6 + length)
+            .ok_or_else(|| invalid("truncated record"))?;
+
+        rest = &rest[0x6 + length..];
+        found.push(tag);
+
+        match tag {
+            MEMORY if data.len() == MEMORY_SIZE => state.memory.copy_from_slice(data),
+            IP => state.ip = word(data)?,
+            REGISTERS if data.len() == 0x8 => {
+                for (register, value) in state.registers.iter_mut().zip(parse_words(data)?) {
+                    *register = value;
+                }
+            }
+            STACK => state.stack = parse_words(data)?,
+            CALL_STACK => state.call_stack = parse_words(data)?,
+            TRAP_HANDLER => state.trap_handler = Some(word(data)?),
+            OPTIONS if data.len() == 0x3 => {
+                state.strict_decoding = data[0] != 0;
+                state.memory_access = match data[1] {
+                    0 => MemoryAccess::Fault,
+                    1 => MemoryAccess::Wrap,
+                    _ => return Err(invalid("unknown memory access mode")),
+                };
+                state.division_by_zero = match data[2] {
+                    0 => DivisionByZero::Fault,
+                    1 => DivisionByZero::Trap,
+                    _ => return Err(invalid("unknown division by zero mode")),
+                };
+            }
+            GAS => state.gas = Some(long(data)?),
+            COST_TABLE if data.len() == (Opcode::ALL.len() + 0x3) * 0x8 => {
+                let mut costs = data.chunks(0x8).map(long);
+                let mut table = CostTable::zero();
+
+                for op_code in Opcode::ALL.iter() {
+                    table.set_opcode(*op_code, costs.next().unwrap_or(Ok(0))?);
+                }
+                table.register = costs.next().unwrap_or(Ok(0))?;
+                table.constant = costs.next().unwrap_or(Ok(0))?;
+                table.memory = costs.next().unwrap_or(Ok(0))?;
+
+                state.cost_table = table;
+            }
+            INSTRUCTION_COUNT => state.instruction_count = long(data)?,
+            MEMORY | REGISTERS | OPTIONS | COST_TABLE => {
+                return Err(invalid(&format!("record 0x{:x} has the wrong size", tag)))
+            }
+            // Added by a later version
+            _ => {}
+        }
+    }
+
+    for required in [MEMORY, IP, REGISTERS].iter() {
+        if !found.contains(required) {
+            return Err(invalid(&format!("missing record 0x{:x}", required)));
+        }
+    }
+
+    vm.memory = state.memory;
+    vm.ip = state.ip;
+    vm.registers = state.registers;
+    vm.stack = state.stack;
+    vm.call_stack = state.call_stack;
+    vm.trap_handler = state.trap_handler;
+    vm.strict_decoding = state.strict_decoding;
+    vm.memory_access = state.memory_access;
+    vm.division_by_zero = state.division_by_zero;
+    vm.gas = state.gas;
+    vm.cost_table = state.cost_table;
+    vm.instruction_count = state.instruction_count;
+    // The recorded history belongs to the replaced state
+    vm.history.clear();
+
+    Ok(())
+}
+
+fn invalid(message: &str) -> RsmiscError {
+    RsmiscError::InvalidSnapshot {
+        message: message.to_string(),
+    }
+}
+
+fn record(bytes: &mut Vec<u8>, tag: u16, data: &[u8]) {
+    bytes.extend_from_slice(&tag.to_be_bytes());
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(data);
+}
+
+fn words(values: &[u16]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_be_bytes().to_vec())
+        .collect()
+}
+
+fn word(data: &[u8]) -> Result<u16, RsmiscError> {
+    match data {
+        [high, low] => Ok(u16::from_be_bytes([*high, *low])),
+        _ => Err(invalid("expected a 16-bit value")),
+    }
+}
+
+fn long(data: &[u8]) -> Result<u64, RsmiscError> {
+    let mut value = [0; 0x8];
+
+    if data.len() != value.len() {
+        return Err(invalid("expected a 64-bit value"));
+    }
+
+    value.copy_from_slice(data);
+    Ok(u64::from_be_bytes(value))
+}
+
+// is_multiple_of needs Rust 1.87
+#[allow(clippy::manual_is_multiple_of)]
+fn parse_words(data: &[u8]) -> Result<Vec<u16>, RsmiscError> {
+    if data.len() % 2 != 0 {
+        return Err(invalid("odd number of bytes in a list of 16-bit values"));
+    }
+
+    data.chunks(2).map(word).collect()
+}
+
+// CRC-32 (IEEE 802.3), the checksum used by zip and PNG
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in bytes {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
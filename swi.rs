@@ -0,0 +1,97 @@
+use crate::console::Console;
+
+// Built-in software interrupts (the number is the SWI operand)
+pub const PRINT_CHARACTER: u16 = 0x0;
+pub const PRINT_NUMBER: u16 = 0x1;
+pub const INSTALL_TRAP_HANDLER: u16 = 0x2;
+pub const READ_CHARACTER: u16 = 0x3;
+pub const READ_NUMBER: u16 = 0x4;
+pub const READ_LINE: u16 = 0x5;
+// Strings are decoded as UTF-8 (invalid sequences print as U+FFFD) and never wrap around the
+// end of memory: a string without a NUL before 0xFFFF, or a length reaching past it, raises
+// STRING_OUT_OF_BOUNDS without printing anything
+pub const PRINT_STRING: u16 = 0x6;
+pub const PRINT_STRING_LENGTH: u16 = 0x7;
+// Formatted numbers print R1, padded to at least R2 characters with spaces (R3 = 0) or zeros
+// (any other R3). Zeros go after the sign of negative numbers
+pub const PRINT_SIGNED: u16 = 0x8;
+pub const PRINT_UNSIGNED: u16 = 0x9;
+pub const PRINT_HEX: u16 = 0xA;
+pub const PRINT_BINARY: u16 = 0xB;
+// Radix taken from R4 (2 to 36), digits past 9 are uppercase letters
+pub const PRINT_RADIX: u16 = 0xC;
+
+// Formats `value` the way the formatted number interrupts print it, `None` for an invalid radix
+pub fn format_number(
+    value: u16,
+    signed: bool,
+    radix: u32,
+    width: usize,
+    zero_pad: bool,
+) -> Option<String> {
+    if !(2..=36).contains(&radix) {
+        return None;
+    }
+
+    let negative = signed && (value as i16) < 0;
+    let mut magnitude = if negative {
+        (value as i16).unsigned_abs()
+    } else {
+        value
+    } as u32;
+    let mut digits = Vec::new();
+
+    loop {
+        digits.push(std::char::from_digit(magnitude % radix, radix)?.to_ascii_uppercase());
+        magnitude /= radix;
+
+        if magnitude == 0 {
+            break;
+        }
+    }
+
+    let sign = if negative { "-" } else { "" };
+    let length = sign.len() + digits.len();
+    let padding = width.saturating_sub(length);
+    let digits: String = digits.iter().rev().collect();
+
+    Some(if zero_pad {
+        format!("{}{}{}", sign, "0".repeat(padding), digits)
+    } else {
+        format!("{}{}{}", " ".repeat(padding), sign, digits)
+    })
+}
+
+// Machine state a host handler can access while it runs
+pub struct SwiContext<'a> {
+    // Address of the SWI instruction
+    pub ip: u16,
+    pub number: u16,
+    // R1, R2, R3, R4
+    pub registers: &'a mut [u16; 0x4],
+    pub memory: &'a mut [u8],
+    pub console: &'a mut dyn Console,
+}
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum SwiAction {
+    Continue,
+    // Stores the value in R1 and continues
+    Return(u16),
+    Halt,
+}
+
+// Host function callable from guest programs with `Rsmisc::register_swi`. An error message
+// faults the machine with SOFTWARE_INTERRUPT_FAULT
+pub trait SwiHandler {
+    fn handle(&mut self, context: &mut SwiContext<'_>) -> Result<SwiAction, String>;
+}
+
+impl<F> SwiHandler for F
+where
+    F: FnMut(&mut SwiContext<'_>) -> Result<SwiAction, String>,
+{
+    fn handle(&mut self, context: &mut SwiContext<'_>) -> Result<SwiAction, String> {
+        self(context)
+    }
+}
@@ -0,0 +1,93 @@
+use rsmisc::asm::{assemble, assemble_with_symbols, parse_register};
+use rsmisc::operand::Operand;
+
+fn error(source: &str) -> (usize, String) {
+    let error = assemble(source).unwrap_err();
+    (error.line, error.message)
+}
+
+#[test]
+fn labels_resolve_forward_and_backward() {
+    let source = "\
+start:  CALL #end       ; forward reference
+        LD data
+end:    BZ R1 #start
+data:   .word 1234
+";
+    let assembly = assemble_with_symbols(source).unwrap();
+
+    assert_eq!(assembly.labels["start"], 0x0);
+    assert_eq!(assembly.labels["end"], 0xc);
+    assert_eq!(assembly.labels["data"], 0x12);
+    // CALL #end, then LD data with its immediates little-endian
+    assert_eq!(
+        &assembly.image[..0xc],
+        &[0x0a, 0x23, 0x0c, 0x00, 0x00, 0x00, 0x06, 0x2a, 0x12, 0x00, 0x00, 0x00]
+    );
+    assert_eq!(
+        assembly.source_lines.into_iter().collect::<Vec<_>>(),
+        vec![(0x0, 1), (0x6, 2), (0xc, 3)]
+    );
+}
+
+#[test]
+fn data_directives_emit_bytes_and_big_endian_words() {
+    let image = assemble("here: .byte 1 FF\n.word 1234, here\n.byte 0x7").unwrap();
+
+    assert_eq!(image, vec![0x01, 0xff, 0x12, 0x34, 0x00, 0x00, 0x07]);
+}
+
+#[test]
+fn out_of_range_values_are_rejected() {
+    assert_eq!(
+        error("NOP\nLD #10000"),
+        (2, "value out of range: 10000".to_string())
+    );
+    assert_eq!(
+        error("NOP\n\n.byte 100"),
+        (3, "byte out of range: 100".to_string())
+    );
+}
+
+#[test]
+fn unknown_mnemonics_and_operand_counts_are_rejected() {
+    assert_eq!(
+        error("NOP\nJMP #0"),
+        (2, "unknown mnemonic 'JMP'".to_string())
+    );
+    assert_eq!(
+        error("add R1"),
+        (1, "ADD expects 2 operand(s), found 1".to_string())
+    );
+    assert_eq!(
+        error("NOP\nLD #nowhere"),
+        (2, "undefined label 'nowhere'".to_string())
+    );
+}
+
+#[test]
+fn duplicate_labels_are_rejected() {
+    assert_eq!(
+        error("loop: NOP\nloop: HALT"),
+        (2, "duplicate label 'loop'".to_string())
+    );
+}
+
+#[test]
+fn labels_must_not_look_like_numbers_or_registers() {
+    assert_eq!(
+        error("NOP\nface: HALT"),
+        (2, "invalid label 'face'".to_string())
+    );
+    assert_eq!(error("r2: HALT"), (1, "invalid label 'r2'".to_string()));
+    assert_eq!(error("1st: HALT"), (1, "invalid label '1st'".to_string()));
+    assert!(assemble("facade_: HALT").is_ok());
+}
+
+#[test]
+fn registers_are_named_in_any_case() {
+    assert_eq!(parse_register("r2"), Some(Operand::R2));
+    assert_eq!(parse_register("IP"), Some(Operand::IP));
+    assert_eq!(parse_register("CT"), None);
+    assert_eq!(parse_register("R5"), None);
+}
@@ -0,0 +1,64 @@
+use std::sync::{Arc, Mutex};
+
+use rsmisc::asm::assemble_with_symbols;
+use rsmisc::calls::{CallChange, CallStack, Symbols};
+use rsmisc::console::NullConsole;
+use rsmisc::trace::TraceRecord;
+use rsmisc::Rsmisc;
+
+const PROGRAM: &str = "\
+        CALL #outer
+        NOP
+        HALT
+outer:  CALL #inner
+        NOP
+        RET
+inner:  RET
+";
+
+#[test]
+fn symbols_fall_back_to_the_address() {
+    let symbols = Symbols::new(&assemble_with_symbols(PROGRAM).unwrap().labels);
+
+    assert_eq!(symbols.name(0x12), "outer");
+    assert_eq!(symbols.name(0x6), "0x0006");
+    assert_eq!(symbols.labels().get(&0x24), Some(&"inner".to_string()));
+}
+
+#[test]
+fn call_stack_follows_calls_and_returns() {
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let mut vm = Rsmisc::new(&assemble_with_symbols(PROGRAM).unwrap().image).unwrap();
+    let mut calls = CallStack::new();
+    let recorded = changes.clone();
+
+    vm.set_console(Box::new(NullConsole));
+    vm.add_tracer(move |record: &TraceRecord| {
+        let change = calls.follow(record);
+        recorded
+            .lock()
+            .unwrap()
+            .push((change, calls.frames().to_vec()));
+        Ok(())
+    });
+    vm.run(100);
+
+    let entered = |entry| CallChange {
+        entered: Some(entry),
+        returned: None,
+    };
+    let returned = |entry| CallChange {
+        entered: None,
+        returned: Some(entry),
+    };
+    assert_eq!(
+        *changes.lock().unwrap(),
+        vec![
+            (entered(0x12), vec![0x12]),
+            (entered(0x24), vec![0x12, 0x24]),
+            (returned(0x24), vec![0x12]),
+            (returned(0x12), vec![]),
+            (CallChange::default(), vec![]),
+        ]
+    );
+}
@@ -0,0 +1,120 @@
+use rsmisc::asm::assemble_with_symbols;
+use rsmisc::chrome_trace::ChromeTrace;
+use rsmisc::console::NullConsole;
+use rsmisc::json::Json;
+use rsmisc::Rsmisc;
+
+// `outer` calls `inner` twice, RET skips the NOP after each CALL
+const PROGRAM: &str = "\
+        CALL #outer
+        NOP
+        HALT
+outer:  CALL #inner
+        NOP
+        CALL #inner
+        NOP
+        RET
+inner:  LD #1
+        ULD R1
+        RET
+";
+
+fn run(trace: &ChromeTrace, limit: u64) -> Vec<Json> {
+    let assembly = assemble_with_symbols(PROGRAM).unwrap();
+    let mut vm = Rsmisc::new(&assembly.image).unwrap();
+
+    vm.set_console(Box::new(NullConsole));
+    vm.add_tracer(trace.clone());
+    vm.run(limit);
+
+    let json = Json::parse(&trace.to_json().to_string()).unwrap();
+    json.get("traceEvents")
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .to_vec()
+}
+
+fn summary(events: &[Json]) -> Vec<(String, String, u64)> {
+    events
+        .iter()
+        .map(|event| {
+            (
+                event.get("ph").and_then(Json::as_str).unwrap().to_string(),
+                event
+                    .get("name")
+                    .and_then(Json::as_str)
+                    .unwrap()
+                    .to_string(),
+                event.get("ts").and_then(Json::as_u64).unwrap(),
+            )
+        })
+        .collect()
+}
+
+fn expected(events: &[(&str, &str, u64)]) -> Vec<(String, String, u64)> {
+    events
+        .iter()
+        .map(|(phase, name, timestamp)| (phase.to_string(), name.to_string(), *timestamp))
+        .collect()
+}
+
+#[test]
+fn calls_become_duration_events_named_by_symbol() {
+    let assembly = assemble_with_symbols(PROGRAM).unwrap();
+    let trace = ChromeTrace::with_symbols(&assembly.labels);
+    let events = run(&trace, 100);
+
+    assert_eq!(
+        summary(&events),
+        expected(&[
+            ("B", "outer", 1),
+            ("B", "inner", 2),
+            ("E", "inner", 5),
+            ("B", "inner", 6),
+            ("E", "inner", 9),
+            ("E", "outer", 10),
+        ])
+    );
+    assert_eq!(
+        events[1]
+            .get("args")
+            .and_then(|args| args.get("caller"))
+            .and_then(Json::as_u64),
+        Some(0x12)
+    );
+}
+
+#[test]
+fn functions_without_symbols_are_named_by_address() {
+    let trace = ChromeTrace::new();
+    let events = run(&trace, 2);
+
+    // Calls still running when the trace is written end at the last instruction
+    assert_eq!(
+        summary(&events),
+        expected(&[
+            ("B", "0x0012", 1),
+            ("B", "0x0030", 2),
+            ("E", "0x0030", 2),
+            ("E", "0x0012", 2),
+        ])
+    );
+}
+
+#[test]
+fn written_trace_is_one_json_object() {
+    let trace = ChromeTrace::new();
+    run(&trace, 100);
+
+    let mut bytes = Vec::new();
+    trace.write(&mut bytes).unwrap();
+
+    let json = Json::parse(String::from_utf8(bytes).unwrap().trim()).unwrap();
+    assert_eq!(
+        json.get("traceEvents")
+            .and_then(Json::as_array)
+            .map(|events| events.len()),
+        Some(6)
+    );
+}
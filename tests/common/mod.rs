@@ -0,0 +1,10 @@
+use rsmisc::asm::assemble;
+use rsmisc::console::NullConsole;
+use rsmisc::Rsmisc;
+
+// Assembles `source` into a machine whose console output is discarded
+pub fn machine(source: &str) -> Rsmisc {
+    let mut vm = Rsmisc::new(&assemble(source).unwrap()).unwrap();
+    vm.set_console(Box::new(NullConsole));
+    vm
+}
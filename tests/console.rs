@@ -0,0 +1,134 @@
+mod common;
+
+use common::machine;
+use rsmisc::asm::assemble;
+use rsmisc::console::{BufferConsole, Console, NullConsole};
+use rsmisc::Rsmisc;
+
+#[test]
+fn software_interrupts_write_to_console() {
+    let console = BufferConsole::new();
+    let mut vm = machine("MOV R1 #48\nSWI #0\nMOV R1 #2A\nSWI #1\nHALT");
+
+    vm.set_console(Box::new(console.clone()));
+    while vm.execute_next(false).unwrap() {}
+
+    assert_eq!(console.output_string(), "H42");
+}
+
+#[test]
+fn instruction_trace_writes_to_console() {
+    let console = BufferConsole::new();
+    let mut vm = machine("NOP\nHALT");
+
+    vm.set_console(Box::new(console.clone()));
+    while vm.execute_next(true).unwrap() {}
+
+    assert_eq!(console.output_string(), "0x0:\t NOP\n0x6:\t HALT\n");
+}
+
+#[test]
+fn buffer_console_is_shared_across_threads() {
+    let console = BufferConsole::with_input(b"A");
+    let mut guest = console.clone();
+
+    std::thread::spawn(move || {
+        let mut buffer = [0; 0x1];
+        assert_eq!(guest.read(&mut buffer).unwrap(), 1);
+        guest.write(&buffer).unwrap();
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(console.output_string(), "A");
+}
+
+#[test]
+fn null_console_discards_output() {
+    let mut vm = machine("MOV R1 #48\nSWI #0\nHALT");
+
+    vm.set_console(Box::new(NullConsole));
+    while vm.execute_next(true).unwrap() {}
+}
+
+#[test]
+fn input_interrupts_read_from_console() {
+    let console = BufferConsole::with_input("é 123 -2\nhello world\n".as_bytes());
+    let mut vm = machine(
+        "
+        SWI #3          ; read character
+        BZ R4 #done
+        SWI #0
+        SWI #4          ; read number
+        SWI #1
+        SWI #4
+        ADD R1 #2
+        ULD R1
+        SWI #1
+        MOV R1 #100
+        MOV R2 #5
+        SWI #5          ; read line
+        MOV R1 R2
+        SWI #1
+        MOV R1 #200
+        MOV R2 #20
+        SWI #5
+        MOV R1 R4
+        SWI #1
+        HALT
+done:   HALT
+",
+    );
+
+    vm.set_console(Box::new(console.clone()));
+    vm.write_memory(0x200, &[0xff]).unwrap();
+    while vm.execute_next(false).unwrap() {}
+
+    assert_eq!(console.output_string(), "é123050");
+    // The line is cut to R2 bytes and terminated, end of input stores an empty line
+    assert_eq!(vm.read_memory(0x100, 6).unwrap(), b"hello\0");
+    assert_eq!(vm.read_memory(0x200, 1).unwrap(), &[0x0]);
+}
+
+#[test]
+fn string_interrupts_print_from_memory() {
+    let console = BufferConsole::new();
+    let mut vm = machine(
+        "
+        MOV R1 #text
+        SWI #6
+        MOV R1 #text
+        MOV R2 #3
+        SWI #7
+        HALT
+text:   .byte 48 C3 A9 6C 6C 6F 0
+",
+    );
+
+    vm.set_console(Box::new(console.clone()));
+    while vm.execute_next(false).unwrap() {}
+
+    assert_eq!(console.output_string(), "HélloHé");
+}
+
+#[test]
+fn string_interrupts_fault_past_end_of_memory() {
+    for source in &["MOV R1 #FFFE\nSWI #6", "MOV R1 #FFFE\nMOV R2 #3\nSWI #7"] {
+        // No NUL anywhere after the code
+        let mut program = assemble(source).unwrap();
+        program.resize(0x10000, 0x41);
+
+        let console = BufferConsole::new();
+        let mut vm = Rsmisc::new(&program).unwrap();
+        vm.set_console(Box::new(console.clone()));
+
+        let error = loop {
+            if let Err(error) = vm.execute_next(false) {
+                break error;
+            }
+        };
+
+        assert_eq!(error.code(), -14);
+        assert_eq!(console.output_string(), "");
+    }
+}
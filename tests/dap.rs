@@ -0,0 +1,272 @@
+use std::io::Cursor;
+
+use rsmisc::dap::{self, is_source, load};
+use rsmisc::json::Json;
+
+const PROGRAM: &str = "\
+; Prints 'A' from a subroutine
+start:  CALL #print
+        NOP
+        HALT
+print:  LD #41
+        ULD R1
+        SWI #0
+        RET
+";
+
+fn frame(request: Json) -> String {
+    let body = request.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+fn request(seq: u64, command: &str, arguments: Json) -> String {
+    frame(Json::object(vec![
+        ("seq", Json::from(seq)),
+        ("type", Json::from("request")),
+        ("command", Json::from(command)),
+        ("arguments", arguments),
+    ]))
+}
+
+// Runs a session with the requests and returns the messages the adapter sent
+fn session(name: &str, requests: &[(&str, Json)]) -> Vec<Json> {
+    let file = format!("rsmisc-dap-{}-{}.asm", name, std::process::id());
+    let path = std::env::temp_dir().join(file);
+    std::fs::write(&path, PROGRAM).unwrap();
+
+    let mut input = request(
+        1,
+        "launch",
+        Json::object(vec![
+            ("program", Json::from(path.to_str().unwrap())),
+            ("stopOnEntry", Json::from(true)),
+        ]),
+    );
+    input.push_str(&request(
+        2,
+        "setBreakpoints",
+        Json::object(vec![
+            (
+                "source",
+                Json::object(vec![("path", Json::from(path.to_str().unwrap()))]),
+            ),
+            (
+                "breakpoints",
+                Json::from(vec![Json::object(vec![("line", Json::from(5u64))])]),
+            ),
+        ]),
+    ));
+    for (index, (command, arguments)) in requests.iter().enumerate() {
+        input.push_str(&request(index as u64 + 3, command, arguments.clone()));
+    }
+
+    let mut output = Vec::new();
+    dap::serve(Cursor::new(input), &mut output).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    output
+        .split("Content-Length: ")
+        .skip(1)
+        .map(|message| Json::parse(message.split_once("\r\n\r\n").unwrap().1).unwrap())
+        .collect()
+}
+
+fn events<'a>(messages: &'a [Json], event: &str) -> Vec<&'a Json> {
+    messages
+        .iter()
+        .filter(|message| message.get("event").and_then(Json::as_str) == Some(event))
+        .collect()
+}
+
+fn response<'a>(messages: &'a [Json], command: &str) -> &'a Json {
+    messages
+        .iter()
+        .find(|message| message.get("command").and_then(Json::as_str) == Some(command))
+        .unwrap()
+}
+
+fn stop_reasons(messages: &[Json]) -> Vec<&str> {
+    events(messages, "stopped")
+        .iter()
+        .filter_map(|event| event.get("body")?.get("reason")?.as_str())
+        .collect()
+}
+
+#[test]
+fn breakpoints_map_to_source_lines() {
+    let messages = session("breakpoints", &[("configurationDone", Json::Null)]);
+    let breakpoint = &response(&messages, "setBreakpoints")
+        .get("body")
+        .unwrap()
+        .get("breakpoints")
+        .unwrap()
+        .as_array()
+        .unwrap()[0];
+
+    // Line 5 is the first instruction of `print`
+    assert_eq!(breakpoint.get("verified"), Some(&Json::Bool(true)));
+    assert_eq!(breakpoint.get("line").and_then(Json::as_u64), Some(5));
+    assert_eq!(events(&messages, "initialized").len(), 1);
+    assert_eq!(stop_reasons(&messages), ["entry"]);
+}
+
+#[test]
+fn breakpoints_in_other_files_are_unverified() {
+    let other = Json::object(vec![
+        (
+            "source",
+            Json::object(vec![("path", Json::from("other.asm"))]),
+        ),
+        (
+            "breakpoints",
+            Json::from(vec![Json::object(vec![("line", Json::from(3u64))])]),
+        ),
+    ]);
+    let messages = session(
+        "other",
+        &[
+            ("setBreakpoints", other),
+            ("configurationDone", Json::Null),
+            ("continue", Json::Null),
+        ],
+    );
+    let responses: Vec<&Json> = messages
+        .iter()
+        .filter(|message| message.get("command").and_then(Json::as_str) == Some("setBreakpoints"))
+        .collect();
+    let breakpoint = &responses[1]
+        .get("body")
+        .unwrap()
+        .get("breakpoints")
+        .unwrap()
+        .as_array()
+        .unwrap()[0];
+
+    // The breakpoint in the program still stops it
+    assert_eq!(breakpoint.get("verified"), Some(&Json::Bool(false)));
+    assert_eq!(stop_reasons(&messages), ["entry", "breakpoint"]);
+}
+
+#[test]
+fn continue_stops_at_breakpoint_with_stack_trace() {
+    let messages = session(
+        "stack",
+        &[
+            ("configurationDone", Json::Null),
+            ("continue", Json::Null),
+            (
+                "stackTrace",
+                Json::object(vec![("threadId", Json::from(1u64))]),
+            ),
+        ],
+    );
+    let frames = response(&messages, "stackTrace")
+        .get("body")
+        .unwrap()
+        .get("stackFrames")
+        .unwrap()
+        .as_array()
+        .unwrap();
+    let summary: Vec<(&str, u64)> = frames
+        .iter()
+        .map(|frame| {
+            (
+                frame.get("name").and_then(Json::as_str).unwrap(),
+                frame.get("line").and_then(Json::as_u64).unwrap(),
+            )
+        })
+        .collect();
+
+    assert_eq!(stop_reasons(&messages), ["entry", "breakpoint"]);
+    assert_eq!(summary, [("print", 5), ("start", 2)]);
+}
+
+#[test]
+fn registers_stack_and_stepping() {
+    let messages = session(
+        "step",
+        &[
+            ("configurationDone", Json::Null),
+            ("continue", Json::Null),
+            ("stepIn", Json::object(vec![("threadId", Json::from(1u64))])),
+            (
+                "variables",
+                Json::object(vec![("variablesReference", Json::from(2u64))]),
+            ),
+            (
+                "stepOut",
+                Json::object(vec![("threadId", Json::from(1u64))]),
+            ),
+            (
+                "variables",
+                Json::object(vec![("variablesReference", Json::from(1u64))]),
+            ),
+            ("continue", Json::Null),
+            ("disconnect", Json::Null),
+        ],
+    );
+    let variables: Vec<Vec<(String, String)>> = messages
+        .iter()
+        .filter(|message| message.get("command").and_then(Json::as_str) == Some("variables"))
+        .map(|message| {
+            message
+                .get("body")
+                .unwrap()
+                .get("variables")
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|variable| {
+                    (
+                        variable.get("name").unwrap().as_str().unwrap().to_string(),
+                        variable.get("value").unwrap().as_str().unwrap().to_string(),
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    let output: Vec<&str> = events(&messages, "output")
+        .iter()
+        .filter_map(|event| event.get("body")?.get("output")?.as_str())
+        .collect();
+
+    assert_eq!(
+        stop_reasons(&messages),
+        ["entry", "breakpoint", "step", "step"]
+    );
+    assert_eq!(variables[0], [("[0]".to_string(), "0x0041".to_string())]);
+    // RET resumes two instructions after the CALL
+    assert_eq!(variables[1][0], ("R1".to_string(), "0x0041".to_string()));
+    assert_eq!(variables[1][4], ("IP".to_string(), "0x000C".to_string()));
+    assert_eq!(output, ["A"]);
+    assert_eq!(events(&messages, "terminated").len(), 1);
+}
+
+#[test]
+fn load_assembles_sources_and_reads_images() {
+    let directory = std::env::temp_dir();
+    let source = directory.join(format!("rsmisc-load-{}.asm", std::process::id()));
+    let image = directory.join(format!("rsmisc-load-{}.bin", std::process::id()));
+    std::fs::write(&source, "start: HALT").unwrap();
+    std::fs::write(&image, [0x0, 0x0, 0x0, 0x0, 0x0, 0x0]).unwrap();
+
+    let assembled = load(source.to_str().unwrap());
+    let read = load(image.to_str().unwrap());
+    std::fs::remove_file(&source).unwrap();
+    std::fs::remove_file(&image).unwrap();
+
+    let assembled = assembled.unwrap();
+    assert_eq!(assembled.image, vec![0x0; 6]);
+    assert_eq!(assembled.labels.get("start"), Some(&0x0));
+
+    let read = read.unwrap();
+    assert_eq!(read.image, vec![0x0; 6]);
+    assert!(read.labels.is_empty());
+    assert!(read.source_lines.is_empty());
+
+    assert!(is_source("program.s"));
+    assert!(!is_source("program.bin"));
+    assert!(load("/nonexistent/program.asm").is_err());
+}
@@ -0,0 +1,171 @@
+use rsmisc::asm::assemble;
+use rsmisc::disasm::{disassemble, disassemble_recursive, Disassembly, LineKind};
+use rsmisc::instruction::Opcode;
+
+const PROGRAM: &str = "\
+        LD 100
+        CALL #sub
+        HALT
+sub:    ULD 102
+        RET
+        .byte 7
+";
+
+#[test]
+fn linear_sweep_decodes_every_instruction() {
+    let disassembly = disassemble(&assemble(PROGRAM).unwrap());
+    let lines: Vec<(u16, Option<Opcode>)> = disassembly
+        .lines
+        .iter()
+        .map(|line| match &line.kind {
+            LineKind::Instruction(instruction) => (line.address, Some(instruction.op_code)),
+            LineKind::Data => (line.address, None),
+        })
+        .collect();
+
+    // The trailing byte does not form an instruction
+    assert_eq!(
+        lines,
+        vec![
+            (0x0, Some(Opcode::LD)),
+            (0x6, Some(Opcode::CALL)),
+            (0xc, Some(Opcode::HALT)),
+            (0x12, Some(Opcode::ULD)),
+            (0x18, Some(Opcode::RET)),
+            (0x1e, None),
+        ]
+    );
+    assert_eq!(disassembly.lines[5].bytes, vec![0x7]);
+}
+
+#[test]
+fn branch_targets_get_labels() {
+    let disassembly = disassemble(&assemble(PROGRAM).unwrap());
+
+    assert_eq!(
+        disassembly.labels.into_iter().collect::<Vec<_>>(),
+        vec![(0x12, "L_0012".to_string())]
+    );
+}
+
+#[test]
+fn memory_operands_are_cross_referenced() {
+    let disassembly = disassemble(&assemble(PROGRAM).unwrap());
+
+    assert_eq!(
+        disassembly.references.into_iter().collect::<Vec<_>>(),
+        vec![(0x100, vec![0x0]), (0x102, vec![0x12])]
+    );
+}
+
+#[test]
+fn lines_are_found_by_their_address() {
+    let disassembly = disassemble(&assemble(PROGRAM).unwrap());
+
+    assert_eq!(
+        disassembly.line_at(0x12).map(|line| line.address),
+        Some(0x12)
+    );
+    assert_eq!(
+        disassembly.line_at(0x1e).map(|line| line.address),
+        Some(0x1e)
+    );
+    assert!(disassembly.line_at(0x13).is_none());
+    assert!(disassembly.line_at(0x24).is_none());
+}
+
+#[test]
+fn listing_shows_labels_data_and_references() {
+    let listing = disassemble(&assemble(PROGRAM).unwrap()).to_string();
+
+    assert!(listing.contains("CALL #L_0012\n"), "{}", listing);
+    assert!(listing.contains("L_0012:\n    0012"), "{}", listing);
+    assert!(listing.contains(".byte 7"), "{}", listing);
+    assert!(listing.contains("; 0100  0000 LD\n"), "{}", listing);
+    assert!(listing.contains("; 0102  0012 ULD\n"), "{}", listing);
+}
+
+fn kinds(disassembly: &Disassembly) -> Vec<(u16, bool)> {
+    disassembly
+        .lines
+        .iter()
+        .map(|line| (line.address, matches!(line.kind, LineKind::Instruction(_))))
+        .collect()
+}
+
+#[test]
+fn recursive_traversal_separates_code_from_data() {
+    // RET resumes after the NOP, which is never executed
+    let source = "\
+        CALL #sub
+        NOP
+        HALT
+        .word 1234 5678
+sub:    RET
+";
+    let disassembly = disassemble_recursive(&assemble(source).unwrap(), 0x0);
+
+    assert_eq!(
+        kinds(&disassembly),
+        vec![
+            (0x0, true),
+            (0x6, false),
+            (0xc, true),
+            (0x12, false),
+            (0x16, true)
+        ]
+    );
+    assert_eq!(disassembly.lines[3].bytes, vec![0x12, 0x34, 0x56, 0x78]);
+
+    let listing = disassembly.to_string();
+    assert!(listing.contains("CALL #L_0016\n"), "{}", listing);
+    assert!(listing.contains(".word 1234 5678\n"), "{}", listing);
+}
+
+#[test]
+fn computed_jumps_are_guessed_from_register_constants() {
+    // MOV to IP resumes one instruction past `base`
+    let source = "\
+        MOV R2 #base
+        MOV IP R2
+        .word FFFF FFFF FFFF
+base:   .word 0 0 0
+        HALT
+";
+    let disassembly = disassemble_recursive(&assemble(source).unwrap(), 0x0);
+
+    assert_eq!(
+        kinds(&disassembly),
+        vec![
+            (0x0, true),
+            (0x6, true),
+            (0xc, false),
+            (0x12, false),
+            (0x18, true)
+        ]
+    );
+    assert_eq!(
+        disassembly
+            .computed_jumps
+            .clone()
+            .into_iter()
+            .collect::<Vec<_>>(),
+        vec![(0x6, Some(0x12))]
+    );
+    assert_eq!(
+        disassembly.labels.get(&0x12).map(String::as_str),
+        Some("L_0012")
+    );
+    assert!(disassembly
+        .to_string()
+        .contains("MOV IP R2  ; unresolved, likely L_0012\n"));
+}
+
+#[test]
+fn unknown_jump_targets_are_marked_unresolved() {
+    let disassembly = disassemble_recursive(&assemble("LD 100\nULD IP\nHALT").unwrap(), 0x0);
+
+    assert_eq!(disassembly.computed_jumps.get(&0x6).copied(), Some(None));
+    assert!(matches!(disassembly.lines[2].kind, LineKind::Data));
+    assert!(disassembly.to_string().contains("ULD IP  ; unresolved\n"));
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::machine;
+use rsmisc::division_by_zero::DivisionByZero;
+use rsmisc::{Rsmisc, RsmiscError};
+
+fn run(vm: &mut Rsmisc) -> Result<(), RsmiscError> {
+    for _ in 0..100 {
+        if !vm.execute_next(false)? {
+            return Ok(());
+        }
+    }
+
+    panic!("program did not halt");
+}
+
+const TRAPPING_PROGRAM: &str = "
+        MOV R1 #handler
+        SWI #2
+        MOV R3 #7
+        DIV R3 #0
+        MOV R4 #1
+        HALT
+handler:
+        MOV R2 #2A
+        RET
+";
+
+#[test]
+fn division_by_zero_faults_by_default() {
+    let mut vm = machine("MOV R1 #7\nDIV R1 #0\nHALT");
+
+    match run(&mut vm) {
+        Err(RsmiscError::DivisionByZero { ip, instruction }) => {
+            assert_eq!(ip, 0x6);
+            assert_eq!(instruction.to_string(), "DIV R1 #0");
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn division_by_zero_faults_with_installed_handler_in_fault_mode() {
+    let mut vm = machine(TRAPPING_PROGRAM);
+    vm.set_division_by_zero(DivisionByZero::Fault);
+    let error = run(&mut vm).unwrap_err();
+
+    assert_eq!(error.code(), -10);
+    assert_eq!(error.ip(), Some(0x12));
+}
+
+#[test]
+fn division_by_zero_traps_to_guest_handler() {
+    let mut vm = machine(TRAPPING_PROGRAM);
+    vm.set_division_by_zero(DivisionByZero::Trap);
+
+    run(&mut vm).unwrap();
+
+    // The handler ran and execution resumed after the DIV, with nothing pushed for its result
+    let state = vm.to_string();
+    assert!(state.contains("R2: 0x2a"), "{}", state);
+    assert!(state.contains("R4: 0x1"), "{}", state);
+    assert!(state.contains("SP: 0x0"), "{}", state);
+    assert!(state.contains("CSP: 0x0"), "{}", state);
+}
+
+#[test]
+fn division_by_zero_faults_in_trap_mode_without_handler() {
+    let mut vm = machine("DIV #1 #0\nHALT");
+    vm.set_division_by_zero(DivisionByZero::Trap);
+
+    assert_eq!(run(&mut vm).unwrap_err().code(), -10);
+}
+
+#[test]
+fn division_by_non_zero_is_unaffected() {
+    let mut vm = machine("DIV #2A #7\nULD R1\nHALT");
+    vm.set_division_by_zero(DivisionByZero::Trap);
+
+    run(&mut vm).unwrap();
+    assert!(vm.to_string().contains("R1: 0x6"));
+}
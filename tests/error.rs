@@ -0,0 +1,102 @@
+mod common;
+
+use std::error::Error;
+
+use common::machine;
+use rsmisc::instruction::{DecodeError, Instruction, Opcode};
+use rsmisc::operand::Operand;
+use rsmisc::RsmiscError;
+
+fn fault(source: &str) -> RsmiscError {
+    let mut vm = machine(source);
+
+    loop {
+        match vm.execute_next(false) {
+            Ok(true) => {}
+            Ok(false) => panic!("expected a fault, the machine halted"),
+            Err(error) => return error,
+        }
+    }
+}
+
+#[test]
+fn stack_underflows_name_the_instruction() {
+    let uld = Instruction {
+        op_code: Opcode::ULD,
+        target: Operand::R1,
+        source: Operand::R1,
+        target_imm: 0x0,
+        source_imm: 0x0,
+    };
+    let error = fault("LD #1\nULD R1\nULD R1");
+
+    assert_eq!(
+        error,
+        RsmiscError::NoElementsInStack {
+            ip: 0xc,
+            instruction: uld,
+        }
+    );
+    assert_eq!(error.code(), -4);
+    assert_eq!(error.ip(), Some(0xc));
+    assert_eq!(error.to_string(), "NO_ELEMENTS_IN_STACK (at 0xc: ULD R1)");
+
+    let error = fault("NOP\nRET");
+    assert_eq!(error.code(), -2);
+    assert_eq!(error.name(), "CALL_STACK_EMPTY");
+    assert_eq!(error.to_string(), "CALL_STACK_EMPTY (at 0x6: RET)");
+}
+
+#[test]
+fn codes_match_the_numbers_used_before_errors_were_typed() {
+    assert_eq!(fault("SWI #7F").code(), -3);
+    assert_eq!(fault(".byte 5 23 0 0 0 0").code(), -6);
+    assert_eq!(fault("MOV R1 #7\nDIV R1 #0").code(), -10);
+    assert_eq!(RsmiscError::ProgramTooLarge { size: 0x10001 }.code(), -9);
+}
+
+#[test]
+fn display_names_the_error_and_where_it_happened() {
+    assert_eq!(
+        fault("MOV R1 #7\nDIV R1 #0").to_string(),
+        "DIVISION_BY_ZERO (at 0x6: DIV R1 #0)"
+    );
+    assert_eq!(
+        fault("NOP\nSWI #7F").to_string(),
+        "UNIMPLEMENTED_SOFTWARE_INTERRUPT 0x7f (at 0x6: SWI #7F)"
+    );
+    assert_eq!(
+        RsmiscError::OutOfBounds {
+            ip: 0x12,
+            address: 0xffff,
+            length: 2,
+        }
+        .to_string(),
+        "OUT_OF_BOUNDS: 0x2 bytes at 0xffff (at 0x12)"
+    );
+    assert_eq!(
+        RsmiscError::ProgramTooLarge { size: 0x10001 }.to_string(),
+        "PROGRAM_TOO_LARGE: 0x10001 bytes (memory is 0x10000)"
+    );
+}
+
+#[test]
+fn source_is_the_decode_error() {
+    let error = RsmiscError::IllegalInstruction {
+        ip: 0x6,
+        tword: 0x0d00_0000_0000,
+        error: DecodeError::UnknownOpcode(0x0d),
+    };
+
+    assert_eq!(error.code(), -7);
+    assert_eq!(error.instruction(), None);
+    assert_eq!(
+        error.to_string(),
+        "ILLEGAL_INSTRUCTION: unknown opcode 0xd (0x0d0000000000) (at 0x6)"
+    );
+    assert_eq!(
+        error.source().map(|source| source.to_string()),
+        Some("unknown opcode 0xd".to_string())
+    );
+    assert!(fault("NOP\nRET").source().is_none());
+}
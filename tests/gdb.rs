@@ -0,0 +1,154 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use common::machine;
+use rsmisc::gdb::{self, TARGET_XML};
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    // Starts a server for `source` on a thread
+    fn connect(source: &str) -> (Self, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut vm = machine(source);
+
+        vm.set_history_limit(0x10);
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            stream.set_nodelay(true).unwrap();
+            gdb::serve(&mut vm, stream).unwrap();
+        });
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+
+        (Client { stream }, server)
+    }
+
+    fn request(&mut self, packet: &str) -> String {
+        let checksum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", packet, checksum).unwrap();
+
+        assert_eq!(self.read_byte(), b'+');
+        assert_eq!(self.read_byte(), b'$');
+
+        let mut reply = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => reply.push(byte),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let expected = reply.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+        assert_eq!(
+            u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap(),
+            expected
+        );
+        self.stream.write_all(b"+").unwrap();
+
+        String::from_utf8(reply).unwrap()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0; 1];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+}
+
+#[test]
+fn registers_and_memory() {
+    let (mut client, server) = Client::connect("HALT");
+
+    assert!(client
+        .request("qSupported:swbreak+")
+        .contains("qXfer:features:read+"));
+    assert_eq!(client.request("?"), "S05");
+    assert_eq!(client.request("g"), "00000000000000000000");
+    assert_eq!(client.request("G0001000200030004000c"), "OK");
+    assert_eq!(client.request("g"), "0001000200030004000c");
+    assert_eq!(client.request("p4"), "000c");
+    assert_eq!(client.request("P1=abcd"), "OK");
+    assert_eq!(client.request("p1"), "abcd");
+    assert_eq!(client.request("p7"), "E01");
+
+    assert_eq!(client.request("M100,3:c0ffee"), "OK");
+    assert_eq!(client.request("m100,4"), "c0ffee00");
+    assert_eq!(client.request("mffff,2"), "E01");
+    assert_eq!(client.request("m10,ffffffffffffffff"), "E01");
+    assert_eq!(client.request("m0,2001"), "E01");
+    assert_eq!(client.request("m0,2000").len(), 0x4000);
+
+    assert_eq!(client.request("D"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn target_description() {
+    let (mut client, server) = Client::connect("HALT");
+    let first = client.request("qXfer:features:read:target.xml:0,40");
+    let rest = client.request(&format!(
+        "qXfer:features:read:target.xml:40,{:x}",
+        TARGET_XML.len()
+    ));
+
+    assert!(first.starts_with('m'));
+    assert!(rest.starts_with('l'));
+    assert_eq!(format!("{}{}", &first[1..], &rest[1..]), TARGET_XML);
+
+    assert_eq!(client.request("k"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn step_continue_and_breakpoints() {
+    let (mut client, server) = Client::connect("LD #5\nULD R1\nNOP\nLD #7\nULD 100\nHALT");
+
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("p4"), "0006");
+
+    assert_eq!(client.request("Z0,12,6"), "OK");
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("g"), "00050000000000000012");
+
+    assert_eq!(client.request("z0,12,6"), "OK");
+    assert_eq!(client.request("Z2,100,2"), "OK");
+    assert_eq!(client.request("c"), "T05watch:100;");
+    assert_eq!(client.request("m100,2"), "0007");
+
+    assert_eq!(client.request("c"), "W00");
+    assert_eq!(client.request("D"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn faults_are_reported_as_signals() {
+    let (mut client, server) = Client::connect("DIV #1 #0");
+
+    assert_eq!(client.request("c"), "S08");
+    assert_eq!(client.request("k"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn reverse_step_and_continue() {
+    let (mut client, server) = Client::connect("LD #5\nULD R1\nHALT");
+
+    assert!(client.request("qSupported").contains("ReverseStep+"));
+    assert_eq!(client.request("c"), "W00");
+    assert_eq!(client.request("bs"), "S05");
+    assert_eq!(client.request("g"), "0005000000000000000c");
+    assert_eq!(client.request("bc"), "T05replaylog:begin;");
+    assert_eq!(client.request("g"), "00000000000000000000");
+
+    assert_eq!(client.request("k"), "OK");
+    server.join().unwrap();
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use common::machine;
+use rsmisc::outcome::{Outcome, RunResult};
+use rsmisc::swi::{SwiAction, SwiContext};
+use rsmisc::{Rsmisc, RsmiscError};
+
+// RET resumes after the NOP, so eight instructions run
+const PROGRAM: &str = "\
+        LD #1234
+        ULD 100
+        CALL #sub
+        NOP
+        HALT
+sub:    LD 100
+        ULD R2
+        LD #7
+        RET
+";
+
+type State = (u16, [u16; 4], Vec<u16>, Vec<u16>, Vec<u8>);
+
+fn state(vm: &Rsmisc) -> State {
+    (
+        vm.ip(),
+        vm.registers(),
+        vm.stack().to_vec(),
+        vm.call_stack().to_vec(),
+        vm.memory().to_vec(),
+    )
+}
+
+#[test]
+fn step_back_undoes_every_instruction() {
+    let mut vm = machine(PROGRAM);
+    vm.set_history_limit(0x100);
+
+    let mut states = vec![state(&vm)];
+
+    while vm.execute_next(false).unwrap() {
+        states.push(state(&vm));
+    }
+    assert_eq!(vm.instruction_count(), 8);
+
+    // The HALT is undone first
+    assert!(vm.step_back());
+    while let Some(expected) = states.pop() {
+        assert_eq!(state(&vm), expected);
+        if !states.is_empty() {
+            assert!(vm.step_back());
+        }
+    }
+
+    assert!(!vm.step_back());
+    assert_eq!(vm.instruction_count(), 0);
+    assert_eq!(vm.load_16(0x100).unwrap(), 0);
+}
+
+#[test]
+fn goto_instruction_and_run_forward_again() {
+    let mut vm = machine(PROGRAM);
+    vm.set_history_limit(0x100);
+
+    vm.run(100);
+    vm.goto_instruction(3).unwrap();
+
+    assert_eq!(vm.ip(), 0x1e);
+    assert_eq!(vm.call_stack(), &[0x12]);
+    assert_eq!(vm.instruction_count(), 3);
+    assert_eq!(
+        vm.goto_instruction(4),
+        Err(RsmiscError::NotInHistory { count: 4 })
+    );
+
+    assert_eq!(vm.run(100).outcome, Outcome::Halted);
+    assert_eq!(vm.registers()[1], 0x1234);
+    assert_eq!(vm.instruction_count(), 8);
+}
+
+#[test]
+fn run_back_stops_at_breakpoints_and_history_start() {
+    let mut vm = machine(PROGRAM);
+    vm.set_history_limit(0x100);
+
+    vm.run(100);
+    vm.add_breakpoint(0x1e);
+
+    assert_eq!(
+        vm.run_back(100),
+        RunResult {
+            outcome: Outcome::Breakpoint(0x1e),
+            steps: 5
+        }
+    );
+    assert_eq!(
+        vm.run_back(100),
+        RunResult {
+            outcome: Outcome::StartOfHistory,
+            steps: 3
+        }
+    );
+    assert_eq!(vm.ip(), 0x0);
+}
+
+#[test]
+fn history_is_bounded() {
+    let mut vm = machine(PROGRAM);
+
+    vm.set_history_limit(2);
+    vm.run(100);
+
+    assert_eq!(vm.history().count(), 2);
+    assert_eq!(
+        vm.goto_instruction(5),
+        Err(RsmiscError::NotInHistory { count: 5 })
+    );
+    assert_eq!(vm.run_back(100).steps, 2);
+    assert_eq!(vm.instruction_count(), 6);
+}
+
+#[test]
+fn host_interrupt_memory_writes_are_undone() {
+    let mut vm = machine("SWI #20\nHALT");
+    vm.set_history_limit(0x100);
+
+    vm.register_swi(0x20, |context: &mut SwiContext| {
+        context.memory[0x200] = 0xaa;
+        Ok(SwiAction::Return(0x1))
+    });
+    vm.run(1);
+
+    assert_eq!(vm.memory()[0x200], 0xaa);
+    assert!(vm.step_back());
+    assert_eq!(vm.memory()[0x200], 0x0);
+    assert_eq!(vm.registers()[0], 0x0);
+}
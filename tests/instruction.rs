@@ -0,0 +1,195 @@
+mod common;
+
+use common::machine;
+use rsmisc::instruction::{DecodeError, Instruction, Opcode};
+use rsmisc::operand::{Operand, OperandType};
+use rsmisc::RsmiscError;
+
+// Immediates exercising both bytes, the sign bit and the edges of the range
+const IMMEDIATES: [u16; 8] = [0x0, 0x1, 0xff, 0x100, 0x1234, 0x7fff, 0x8000, 0xffff];
+
+fn round_trip(instruction: Instruction) {
+    let tword = instruction.encode();
+
+    assert_eq!(tword >> 48, 0, "{:?} does not fit in 48 bits", instruction);
+    assert_eq!(Instruction::from(tword), instruction);
+}
+
+#[test]
+fn encode_round_trips_every_opcode_and_operand_combination() {
+    for &op_code in Opcode::ALL.iter() {
+        for &target in Operand::ALL.iter() {
+            for &source in Operand::ALL.iter() {
+                for &target_imm in IMMEDIATES.iter() {
+                    for &source_imm in IMMEDIATES.iter() {
+                        round_trip(Instruction {
+                            op_code,
+                            target,
+                            source,
+                            target_imm,
+                            source_imm,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn encode_round_trips_pseudo_random_immediates() {
+    // Fixed-seed xorshift so failures are reproducible
+    let mut state: u32 = 0x2545_f491;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+
+    for _ in 0..10_000 {
+        let bits = next();
+        let imms = next();
+
+        round_trip(Instruction {
+            op_code: Opcode::ALL[bits as usize % Opcode::ALL.len()],
+            target: Operand::ALL[(bits >> 8) as usize % Operand::ALL.len()],
+            source: Operand::ALL[(bits >> 16) as usize % Operand::ALL.len()],
+            target_imm: (imms >> 16) as u16,
+            source_imm: imms as u16,
+        });
+    }
+}
+
+#[test]
+fn decode_encode_round_trips_every_valid_tword_header() {
+    for &op_code in Opcode::ALL.iter() {
+        for operand_combination in 0..49u64 {
+            let tword =
+                ((op_code.to_byte() as u64) << 40) | (operand_combination << 32) | 0x3412_7856;
+
+            assert_eq!(Instruction::from(tword).encode(), tword);
+        }
+    }
+}
+
+#[test]
+fn combination_matches_decoder() {
+    for operand_combination in 0..49u8 {
+        let target = Operand::get_combination_target(operand_combination);
+        let source = Operand::get_combination_source(operand_combination);
+
+        assert_eq!(Operand::combination(target, source), operand_combination);
+    }
+}
+
+#[test]
+fn to_bytes_matches_memory_layout() {
+    let instruction = Instruction {
+        op_code: Opcode::MOV,
+        target: Operand::R2,
+        source: Operand::CT,
+        target_imm: 0x1234,
+        source_imm: 0xabcd,
+    };
+
+    assert_eq!(instruction.to_bytes(), [0x05, 0x0c, 0x34, 0x12, 0xcd, 0xab]);
+    assert_eq!(instruction.encode(), 0x05_0c_34_12_cd_ab);
+}
+
+#[test]
+fn decode_strict_rejects_invalid_twords() {
+    assert_eq!(
+        Instruction::decode_strict(0x0d00_0000_0000),
+        Err(DecodeError::UnknownOpcode(0x0d))
+    );
+    assert_eq!(
+        Instruction::decode_strict(0x0131_0000_0000),
+        Err(DecodeError::OperandCombinationOutOfRange(0x31))
+    );
+    assert_eq!(
+        Instruction::decode_strict(0x0523_0000_0000),
+        Err(DecodeError::OperandNotAllowed {
+            op_code: Opcode::MOV,
+            operand_type: OperandType::TARGET,
+            operand: Operand::CT,
+        })
+    );
+}
+
+#[test]
+fn decode_strict_accepts_every_encodable_instruction() {
+    for &op_code in Opcode::ALL.iter() {
+        for &target in Operand::ALL.iter() {
+            for &source in Operand::ALL.iter() {
+                let instruction = Instruction {
+                    op_code,
+                    target,
+                    source,
+                    target_imm: 0x1234,
+                    source_imm: 0x5678,
+                };
+                let allowed = op_code.allows(OperandType::TARGET, target)
+                    && op_code.allows(OperandType::SOURCE, source);
+
+                assert_eq!(
+                    Instruction::decode_strict(instruction.encode()).is_ok(),
+                    allowed
+                );
+            }
+        }
+    }
+}
+
+// NOP, an unknown opcode, then a MOV with a register it may not write
+const ILLEGAL: &str = "NOP\n.byte D 0 0 0 0 0\nMOV R1 #1\n.byte 5 23 0 0 0 0\nHALT";
+
+#[test]
+fn strict_machine_faults_on_illegal_instructions() {
+    let mut vm = machine(ILLEGAL);
+    vm.set_strict_decoding(true);
+
+    assert_eq!(vm.execute_next(false), Ok(true));
+    assert_eq!(
+        vm.execute_next(false),
+        Err(RsmiscError::IllegalInstruction {
+            ip: 0x6,
+            tword: 0x0d00_0000_0000,
+            error: DecodeError::UnknownOpcode(0x0d),
+        })
+    );
+
+    let mut vm = machine(".byte 5 23 0 0 0 0");
+    vm.set_strict_decoding(true);
+
+    assert_eq!(
+        vm.execute_next(false),
+        Err(RsmiscError::IllegalInstruction {
+            ip: 0x0,
+            tword: 0x0523_0000_0000,
+            error: DecodeError::OperandNotAllowed {
+                op_code: Opcode::MOV,
+                operand_type: OperandType::TARGET,
+                operand: Operand::CT,
+            },
+        })
+    );
+}
+
+#[test]
+fn lenient_machine_runs_illegal_instructions_as_decoded() {
+    let mut vm = machine(ILLEGAL);
+
+    // The unknown opcode runs as a NOP, the MOV only faults once it executes
+    for _ in 0..3 {
+        assert_eq!(vm.execute_next(false), Ok(true));
+    }
+    assert_eq!(
+        vm.execute_next(false),
+        Err(RsmiscError::InvalidMoveTarget {
+            ip: 0x12,
+            instruction: Instruction::from(0x0523_0000_0000),
+            operand: Operand::CT,
+        })
+    );
+}
@@ -0,0 +1,42 @@
+use rsmisc::json::Json;
+
+#[test]
+fn parse_and_serialize_round_trip() {
+    let text = r#"{"a":[1,-2.5,true,null],"b":"quote \" tab \t é 😀","c":{}}"#;
+    let value = Json::parse(text).unwrap();
+
+    assert_eq!(
+        value.get("a").unwrap().as_array().unwrap()[0].as_u64(),
+        Some(1)
+    );
+    assert_eq!(
+        value.get("b").and_then(Json::as_str),
+        Some("quote \" tab \t é 😀")
+    );
+    assert_eq!(Json::parse(&value.to_string()).unwrap(), value);
+    assert_eq!(
+        Json::parse("\"\\ud83d\\ude00\"").unwrap().as_str(),
+        Some("😀")
+    );
+    assert_eq!(
+        value.to_string(),
+        "{\"a\":[1,-2.5,true,null],\"b\":\"quote \\\" tab \\t é 😀\",\"c\":{}}"
+    );
+}
+
+#[test]
+fn parse_rejects_invalid_documents() {
+    for text in &[
+        "",
+        "{",
+        "[1,]",
+        "{\"a\" 1}",
+        "tru",
+        "\"open",
+        "1 2",
+        "\"\\u+041\"",
+        "\"\\ud83d\\u0041\"",
+    ] {
+        assert!(Json::parse(text).is_err(), "{}", text);
+    }
+}
@@ -0,0 +1,66 @@
+use rsmisc::memory_access::MemoryAccess;
+use rsmisc::{Rsmisc, RsmiscError, MEMORY_SIZE};
+
+fn machine() -> Rsmisc {
+    let mut program = vec![0; MEMORY_SIZE];
+
+    program[..0x4].copy_from_slice(&[0xAA, 0xBB, 0xCC, 0xDD]);
+    program[0xFFFA..].copy_from_slice(&[0x1, 0x2, 0x3, 0x4, 0x5, 0x6]);
+    Rsmisc::new(&program).unwrap()
+}
+
+#[test]
+fn loads_reach_the_last_byte_of_memory() {
+    let vm = machine();
+
+    assert_eq!(vm.load_48(0xFFFA), Ok(0x0102_0304_0506));
+    assert_eq!(vm.load_16(0xFFFE), Ok(0x0506));
+    assert_eq!(
+        vm.load_48(0xFFFB),
+        Err(RsmiscError::OutOfBounds {
+            ip: 0x0,
+            address: 0xFFFB,
+            length: 6,
+        })
+    );
+}
+
+#[test]
+fn faulting_store_at_the_last_byte_writes_nothing() {
+    let mut vm = machine();
+
+    assert_eq!(
+        vm.store_16(0xFFFF, 0x1234),
+        Err(RsmiscError::OutOfBounds {
+            ip: 0x0,
+            address: 0xFFFF,
+            length: 2,
+        })
+    );
+    assert_eq!(vm.load_16(0xFFFE), Ok(0x0506));
+    assert_eq!(vm.load_16(0x0), Ok(0xAABB));
+}
+
+#[test]
+fn wrapping_access_continues_from_address_zero() {
+    let mut vm = machine();
+    vm.set_memory_access(MemoryAccess::Wrap);
+
+    assert_eq!(vm.load_48(0xFFFC), Ok(0x0304_0506_AABB));
+    assert_eq!(vm.load_16(0xFFFF), Ok(0x06AA));
+
+    vm.store_16(0xFFFF, 0x1234).unwrap();
+    assert_eq!(vm.load_16(0xFFFE), Ok(0x0512));
+    assert_eq!(vm.load_16(0x0), Ok(0x34BB));
+}
+
+#[test]
+fn programs_may_fill_but_not_exceed_memory() {
+    assert!(Rsmisc::new(&vec![0; MEMORY_SIZE]).is_ok());
+    assert_eq!(
+        Rsmisc::new(&vec![0; MEMORY_SIZE + 1]).err(),
+        Some(RsmiscError::ProgramTooLarge {
+            size: MEMORY_SIZE + 1
+        })
+    );
+}
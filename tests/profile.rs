@@ -0,0 +1,122 @@
+use rsmisc::asm::assemble_with_symbols;
+use rsmisc::console::NullConsole;
+use rsmisc::instruction::Opcode;
+use rsmisc::profile::{FunctionCost, Profile};
+use rsmisc::Rsmisc;
+
+// `outer` calls `inner` twice, RET skips the NOP after each CALL
+const PROGRAM: &str = "\
+main:   CALL #outer
+        NOP
+        HALT
+outer:  CALL #inner
+        NOP
+        CALL #inner
+        NOP
+        RET
+inner:  LD #1
+        ULD R1
+        RET
+";
+
+fn profile() -> Profile {
+    let assembly = assemble_with_symbols(PROGRAM).unwrap();
+    let profile = Profile::with_symbols(&assembly.labels);
+    let mut vm = Rsmisc::new(&assembly.image).unwrap();
+
+    vm.set_console(Box::new(NullConsole));
+    vm.add_tracer(profile.clone());
+    vm.run(100);
+
+    profile
+}
+
+fn cost(entry: u16, name: &str, inclusive: u64, exclusive: u64) -> FunctionCost {
+    FunctionCost {
+        entry,
+        name: name.to_string(),
+        inclusive,
+        exclusive,
+    }
+}
+
+#[test]
+fn counts_addresses_and_opcodes() {
+    let profile = profile();
+
+    assert_eq!(profile.instructions(), 11);
+    assert_eq!(profile.address_count(0x30), 2);
+    assert_eq!(profile.address_count(0x6), 0);
+    assert_eq!(profile.opcode_count(Opcode::CALL), 3);
+    assert_eq!(profile.opcode_count(Opcode::RET), 3);
+    assert_eq!(profile.opcode_count(Opcode::NOP), 0);
+}
+
+#[test]
+fn functions_are_sorted_by_cost() {
+    assert_eq!(
+        profile().functions(),
+        vec![
+            cost(0x0, "main", 11, 2),
+            cost(0x12, "outer", 9, 3),
+            cost(0x30, "inner", 6, 6),
+        ]
+    );
+}
+
+#[test]
+fn collapsed_stacks_hold_exclusive_counts() {
+    assert_eq!(
+        profile().collapsed_stacks(),
+        "main 2\nmain;outer 3\nmain;outer;inner 6\n"
+    );
+}
+
+#[test]
+fn report_annotates_the_executed_instructions() {
+    let report = profile().report();
+    let lines: Vec<&str> = report.lines().collect();
+
+    assert_eq!(lines[0], "11 instructions executed");
+    assert!(lines[3].ends_with("main"));
+    assert!(lines[5].ends_with("inner"));
+    assert!(report.contains("inner:\n"));
+    assert!(report.contains("0000  CALL #outer"));
+    assert!(report
+        .lines()
+        .any(|line| line.trim_start().starts_with("2   18.18  0030  LD #1")));
+}
+
+#[test]
+fn recursive_calls_are_charged_once() {
+    // `down` calls itself until R1 reaches zero, BZ lands after `base`
+    let source = "\
+main:   MOV R1 #2
+        CALL #down
+        NOP
+        HALT
+down:   BZ R1 #base
+        SUB R1 #1
+        ULD R1
+        CALL #down
+        NOP
+base:   NOP
+        RET
+";
+    let assembly = assemble_with_symbols(source).unwrap();
+    let profile = Profile::with_symbols(&assembly.labels);
+    let mut vm = Rsmisc::new(&assembly.image).unwrap();
+
+    vm.set_console(Box::new(NullConsole));
+    vm.add_tracer(profile.clone());
+    vm.run(100);
+
+    assert_eq!(
+        profile.functions(),
+        vec![cost(0x0, "main", 17, 3), cost(0x18, "down", 14, 14)]
+    );
+    assert_eq!(
+        profile.collapsed_stacks(),
+        "main 3\nmain;down 6\nmain;down;down 6\nmain;down;down;down 2\n"
+    );
+}
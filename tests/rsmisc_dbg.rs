@@ -0,0 +1,141 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static SOURCES: AtomicUsize = AtomicUsize::new(0);
+
+// Runs the debugger on `source` with `input` on stdin and returns what it printed
+fn debug(source: &str, input: &str) -> String {
+    let path = std::env::temp_dir().join(format!(
+        "rsmisc-dbg-{}-{}.asm",
+        std::process::id(),
+        SOURCES.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&path, source).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rsmisc-dbg"))
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    // A debugger waiting on itself never exits, so give up instead of hanging the test run
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > Duration::from_secs(10) {
+            child.kill().unwrap();
+            panic!("the debugger did not exit");
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+const PROGRAM: &str = "\
+        CALL #sub
+        HALT
+        NOP
+sub:    MOV R1 #5
+        RET
+";
+
+#[test]
+fn breakpoints_stop_at_labels() {
+    let output = debug(PROGRAM, "break sub\ninfo breakpoints\ncontinue\nquit\n");
+
+    assert!(
+        output.contains("(rsmisc) Breakpoint at 0x12 <sub>\n"),
+        "{}",
+        output
+    );
+    assert!(output.contains("(rsmisc) 0x12 <sub>\n"), "{}", output);
+    assert!(
+        output.contains("Breakpoint at 0x12 <sub>\nsub:\n=>* 0x12:\t MOV R1 #5\n"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn next_runs_calls_to_completion() {
+    // RET resumes two instructions after the CALL
+    let output = debug(PROGRAM, "next\nregs\nquit\n");
+
+    assert!(output.contains("(rsmisc) =>  0xc:\t NOP\n"), "{}", output);
+    assert!(output.contains("R1: 0x5"), "{}", output);
+
+    let output = debug(PROGRAM, "step\nquit\n");
+    assert!(
+        output.contains("(rsmisc) sub:\n=>  0x12:\t MOV R1 #5\n"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn memory_is_written_and_dumped() {
+    let output = debug("HALT", "write 40 48 69\nx 40 2\nquit\n");
+
+    assert!(output.contains("(rsmisc) 0x0040:  48 69"), "{}", output);
+    assert!(output.contains("  Hi\n"), "{}", output);
+}
+
+#[test]
+fn registers_are_set() {
+    let output = debug(PROGRAM, "set r2 2a\nset IP sub\nregs\nquit\n");
+
+    assert!(output.contains("R2: 0x2a"), "{}", output);
+    assert!(output.contains("IP: 0x12"), "{}", output);
+}
+
+#[test]
+fn disas_lists_instructions_around_an_address() {
+    let output = debug(PROGRAM, "disas sub 3\nquit\n");
+
+    assert!(
+        output.contains("(rsmisc)     0xc:\t NOP\nsub:\n    0x12:\t MOV R1 #5\n    0x18:\t RET\n"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn bad_arguments_are_reported() {
+    let output = debug(
+        PROGRAM,
+        "x\nset R9 1\nwrite 0 100\nbreak nowhere\ndelete 6\nfoo\nquit\n",
+    );
+
+    for message in &[
+        "Missing address or label",
+        "Usage: set <R1|R2|R3|R4|IP> <value>",
+        "Byte out of range: 100",
+        "Invalid number 'nowhere'",
+        "No breakpoint at 0x6",
+        "unknown command 'foo', try 'help'",
+    ] {
+        assert!(output.contains(message), "{}", output);
+    }
+}
+
+#[test]
+fn guest_reads_input_between_commands() {
+    // The guest reads and echoes the character after `continue`, the debugger reads the rest
+    let output = debug("SWI #3\nSWI #0\nHALT", "continue\nz\nquit\n");
+
+    assert!(output.contains("(rsmisc) z"), "{}", output);
+    assert!(output.contains("Halted"), "{}", output);
+}
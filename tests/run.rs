@@ -0,0 +1,111 @@
+mod common;
+
+use common::machine;
+use rsmisc::gas::CostTable;
+use rsmisc::instruction::Opcode;
+use rsmisc::outcome::{Outcome, RunResult};
+use rsmisc::RsmiscError;
+
+#[test]
+fn run_reports_halt_and_step_count() {
+    let mut vm = machine("NOP\nNOP\nHALT");
+
+    assert_eq!(
+        vm.run(100),
+        RunResult {
+            outcome: Outcome::Halted,
+            steps: 3
+        }
+    );
+}
+
+#[test]
+fn run_stops_infinite_loop_at_step_limit() {
+    // MOV IP resumes one instruction past the written address
+    let mut vm = machine("loop: NOP\nMOV IP #loop");
+
+    assert_eq!(
+        vm.run(1000),
+        RunResult {
+            outcome: Outcome::StepLimitReached,
+            steps: 1000
+        }
+    );
+}
+
+#[test]
+fn run_stops_at_breakpoint_and_resumes() {
+    let mut vm = machine("NOP\nNOP\nNOP\nHALT");
+
+    vm.add_breakpoint(0xc);
+
+    assert_eq!(
+        vm.run(100),
+        RunResult {
+            outcome: Outcome::Breakpoint(0xc),
+            steps: 2
+        }
+    );
+    assert_eq!(
+        vm.run(100),
+        RunResult {
+            outcome: Outcome::Halted,
+            steps: 2
+        }
+    );
+}
+
+#[test]
+fn run_reports_faults() {
+    let mut vm = machine("NOP\nRET");
+    let result = vm.run(100);
+
+    assert_eq!(result.steps, 1);
+    match result.outcome {
+        Outcome::Fault(error) => assert_eq!(error.code(), -2),
+        outcome => panic!("unexpected outcome {:?}", outcome),
+    }
+}
+
+#[test]
+fn gas_is_charged_per_opcode_and_operand_kind() {
+    let mut table = CostTable::zero();
+    table.set_opcode(Opcode::MOV, 1);
+    table.set_opcode(Opcode::DIV, 10);
+    table.memory = 5;
+    table.constant = 2;
+
+    let mut vm = machine("MOV R1 R2\nDIV R1 #1\nLD 40\nHALT");
+    vm.set_cost_table(table);
+    vm.set_gas(Some(100));
+
+    assert_eq!(vm.run(1).steps, 1);
+    assert_eq!(vm.remaining_gas(), Some(99));
+    assert_eq!(vm.run(1).steps, 1);
+    assert_eq!(vm.remaining_gas(), Some(87));
+    assert_eq!(vm.run(1).steps, 1);
+    assert_eq!(vm.remaining_gas(), Some(82));
+}
+
+#[test]
+fn out_of_gas_leaves_machine_resumable() {
+    let mut vm = machine("NOP\nDIV #8 #2\nULD R1\nHALT");
+    vm.set_gas(Some(5));
+
+    let result = vm.run(100);
+    assert_eq!(result.steps, 1);
+    match result.outcome {
+        Outcome::Fault(RsmiscError::OutOfGas {
+            ip,
+            required,
+            remaining,
+            ..
+        }) => assert_eq!((ip, required, remaining), (0x6, 8, 4)),
+        outcome => panic!("unexpected outcome {:?}", outcome),
+    }
+
+    // Nothing was executed, so topping up gas retries the same DIV
+    vm.set_gas(Some(100));
+    assert_eq!(vm.run(100).outcome, Outcome::Halted);
+    assert!(vm.to_string().contains("R1: 0x4"));
+}
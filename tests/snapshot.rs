@@ -0,0 +1,129 @@
+mod common;
+
+use common::machine;
+use rsmisc::console::BufferConsole;
+use rsmisc::division_by_zero::DivisionByZero;
+use rsmisc::outcome::Outcome;
+use rsmisc::snapshot::{self, MAGIC, VERSION};
+use rsmisc::RsmiscError;
+
+// Prints 3, 2 and 2 again after the RET (which skips the NOP)
+const PROGRAM: &str = "\
+        LD #3
+        ULD R1
+        SWI #1
+        CALL #sub
+        NOP
+        SWI #1
+        HALT
+sub:    SUB R1 #1
+        ULD R1
+        SWI #1
+        RET
+";
+
+fn record(tag: u16, data: &[u8]) -> Vec<u8> {
+    let mut bytes = tag.to_be_bytes().to_vec();
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+fn seal(mut bytes: Vec<u8>) -> Vec<u8> {
+    let checksum = snapshot::crc32(&bytes);
+    bytes.extend_from_slice(&checksum.to_be_bytes());
+    bytes
+}
+
+#[test]
+fn restored_machine_resumes_where_it_stopped() {
+    let console = BufferConsole::new();
+    let mut vm = machine(PROGRAM);
+    vm.set_console(Box::new(console.clone()));
+
+    vm.set_division_by_zero(DivisionByZero::Trap);
+    vm.set_gas(Some(0x1000));
+    // Inside the subroutine, with a value on each stack
+    vm.run(5);
+    assert_eq!(vm.stack().len(), 1);
+    assert_eq!(vm.call_stack().len(), 1);
+
+    let mut bytes = Vec::new();
+    let printed = console.output().len();
+    vm.save_snapshot(&mut bytes).unwrap();
+    assert_eq!(vm.run(100).outcome, Outcome::Halted);
+
+    let restored_console = BufferConsole::new();
+    let mut restored = machine(PROGRAM);
+    restored.set_console(Box::new(restored_console.clone()));
+    restored.load_snapshot(bytes.as_slice()).unwrap();
+
+    assert_eq!(restored.instruction_count(), 5);
+    assert_eq!(restored.division_by_zero(), DivisionByZero::Trap);
+    assert_eq!(restored.run(100).outcome, Outcome::Halted);
+    assert_eq!(restored.registers(), vm.registers());
+    assert_eq!(restored.call_stack(), vm.call_stack());
+    assert_eq!(restored.remaining_gas(), vm.remaining_gas());
+    assert_eq!(
+        format!(
+            "{}{}",
+            String::from_utf8_lossy(&console.output()[..printed]),
+            restored_console.output_string()
+        ),
+        console.output_string()
+    );
+}
+
+#[test]
+fn corrupted_snapshots_are_rejected() {
+    let mut vm = machine(PROGRAM);
+    let mut bytes = Vec::new();
+    vm.save_snapshot(&mut bytes).unwrap();
+
+    bytes[0x100] ^= 0x1;
+    assert_eq!(
+        vm.load_snapshot(bytes.as_slice()),
+        Err(RsmiscError::InvalidSnapshot {
+            message: "checksum mismatch".to_string()
+        })
+    );
+
+    assert!(vm.load_snapshot(&b"not a snapshot"[..]).is_err());
+    assert!(vm.load_snapshot(&bytes[..bytes.len() - 0x10]).is_err());
+}
+
+#[test]
+fn newer_versions_are_rejected() {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&(VERSION + 1).to_be_bytes());
+
+    let mut vm = machine(PROGRAM);
+    assert!(matches!(
+        vm.load_snapshot(seal(bytes).as_slice()),
+        Err(RsmiscError::InvalidSnapshot { .. })
+    ));
+}
+
+#[test]
+fn missing_optional_and_unknown_records_load() {
+    // Only the required records, as an older writer could produce, plus a record from the future
+    let mut memory = vec![0; rsmisc::MEMORY_SIZE];
+    memory[0x10] = 0xab;
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_be_bytes());
+    bytes.extend(record(0x1, &memory));
+    bytes.extend(record(0x2, &[0x0, 0x6]));
+    bytes.extend(record(0x3, &[0x0, 0x1, 0x0, 0x2, 0x0, 0x3, 0x0, 0x4]));
+    bytes.extend(record(0x7fff, b"ignored"));
+
+    let mut vm = machine(PROGRAM);
+    vm.set_gas(Some(0x10));
+    vm.load_snapshot(seal(bytes).as_slice()).unwrap();
+
+    assert_eq!(vm.memory()[0x10], 0xab);
+    assert_eq!(vm.ip(), 0x6);
+    assert_eq!(vm.registers(), [0x1, 0x2, 0x3, 0x4]);
+    assert!(vm.stack().is_empty());
+    assert_eq!(vm.remaining_gas(), None);
+}
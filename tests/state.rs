@@ -0,0 +1,38 @@
+mod common;
+
+use common::machine;
+use rsmisc::operand::Operand;
+
+#[test]
+fn host_seeds_inputs_and_reads_outputs() {
+    let mut vm = machine("ADD R1 40\nULD 42\nHALT");
+
+    vm.set_register(Operand::R1, 0x10).unwrap();
+    vm.write_memory(0x40, &[0x01, 0x00]).unwrap();
+    vm.run(10);
+
+    assert_eq!(vm.read_memory(0x42, 2).unwrap(), &[0x01, 0x10]);
+    assert_eq!(vm.registers(), [0x10, 0x0, 0x0, 0x0]);
+    assert_eq!(vm.register(Operand::R1), Some(0x10));
+    assert_eq!(vm.ip(), 0x12);
+    assert!(vm.stack().is_empty());
+}
+
+#[test]
+fn stacks_are_visible_and_registers_are_validated() {
+    let mut vm = machine("LD #7\nCALL #sub\nsub: HALT");
+
+    vm.run(10);
+
+    assert_eq!(vm.stack(), &[0x7]);
+    assert_eq!(vm.call_stack(), &[0xc]);
+    assert_eq!(vm.register(Operand::CT), None);
+    assert_eq!(vm.set_register(Operand::MA, 1).unwrap_err().code(), -17);
+    assert_eq!(vm.read_memory(0xffff, 2).unwrap_err().code(), -8);
+    assert!(vm.write_memory(0xfffe, &[1, 2, 3]).is_err());
+    assert_eq!(vm.read_memory(0x10, usize::MAX).unwrap_err().code(), -8);
+    assert_eq!(vm.read_memory(0x0, rsmisc::MEMORY_SIZE).unwrap().len(), 0x10000);
+
+    vm.set_ip(0x6);
+    assert_eq!(vm.register(Operand::IP), Some(0x6));
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use common::machine;
+use rsmisc::asm::assemble_with_symbols;
+use rsmisc::console::BufferConsole;
+use rsmisc::swi::{format_number, SwiAction, SwiContext};
+use rsmisc::Rsmisc;
+
+#[test]
+fn registered_handler_returns_value_in_r1() {
+    let console = BufferConsole::new();
+    let mut vm = machine("MOV R1 #4\nMOV R2 #5\nSWI #40\nSWI #1\nHALT");
+    vm.set_console(Box::new(console.clone()));
+
+    vm.register_swi(0x40, |context: &mut SwiContext| {
+        Ok(SwiAction::Return(
+            context.registers[0] * context.registers[1],
+        ))
+    });
+    while vm.execute_next(false).unwrap() {}
+
+    assert_eq!(console.output_string(), "20");
+}
+
+#[test]
+fn registered_handler_accesses_memory_and_can_halt() {
+    let console = BufferConsole::new();
+    let assembly =
+        assemble_with_symbols("SWI #41\nMOV R1 #1\nSWI #1\nHALT\ndata: .word 0").unwrap();
+    let data = assembly.labels["data"];
+    let mut vm = Rsmisc::new(&assembly.image).unwrap();
+    let mut calls = 0;
+
+    vm.set_console(Box::new(console.clone()));
+    vm.register_swi(0x41, move |context: &mut SwiContext| {
+        calls += 1;
+        context.memory[data as usize + 1] = calls;
+        context.console.write(b"host").unwrap();
+        Ok(SwiAction::Halt)
+    });
+
+    assert!(!vm.execute_next(false).unwrap());
+    assert_eq!(console.output_string(), "host");
+    assert_eq!(vm.load_16(data).unwrap(), 0x1);
+}
+
+#[test]
+fn registered_handler_overrides_built_in_and_faults() {
+    let console = BufferConsole::new();
+    let mut vm = machine("SWI #0\nHALT");
+    vm.set_console(Box::new(console.clone()));
+
+    vm.register_swi(0x0, |_: &mut SwiContext| Err("denied".to_string()));
+    let error = vm.execute_next(false).unwrap_err();
+
+    assert_eq!(error.code(), -13);
+    assert_eq!(
+        error.to_string(),
+        "SOFTWARE_INTERRUPT_FAULT 0x0: denied (at 0x0: SWI #0)"
+    );
+
+    // Built-in behavior comes back once the handler is removed
+    let mut vm = machine("MOV R1 #41\nSWI #0\nHALT");
+    vm.set_console(Box::new(console.clone()));
+    vm.register_swi(0x0, |_: &mut SwiContext| Ok(SwiAction::Continue));
+    assert!(vm.unregister_swi(0x0));
+    while vm.execute_next(false).unwrap() {}
+    assert_eq!(console.output_string(), "A");
+}
+
+#[test]
+fn format_number_pads_signs_and_radixes() {
+    assert_eq!(format_number(0xffd6, true, 10, 0, false).unwrap(), "-42");
+    assert_eq!(format_number(0xffd6, true, 10, 6, true).unwrap(), "-00042");
+    assert_eq!(format_number(0xffd6, true, 10, 6, false).unwrap(), "   -42");
+    assert_eq!(format_number(0x8000, true, 10, 0, false).unwrap(), "-32768");
+    assert_eq!(format_number(0xffd6, false, 10, 0, false).unwrap(), "65494");
+    assert_eq!(format_number(0xbeef, false, 16, 6, true).unwrap(), "00BEEF");
+    assert_eq!(format_number(0x5, false, 2, 8, true).unwrap(), "00000101");
+    assert_eq!(format_number(0x0, false, 36, 0, false).unwrap(), "0");
+    assert_eq!(format_number(0x23, false, 36, 3, false).unwrap(), "  Z");
+    assert_eq!(format_number(0x1, false, 37, 0, false), None);
+}
+
+#[test]
+fn formatted_number_interrupts_use_width_and_padding_registers() {
+    let console = BufferConsole::new();
+    let mut vm = machine(
+        "
+        MOV R1 #FFFF
+        MOV R2 #4
+        SWI #8          ; signed, space padded
+        MOV R3 #1
+        SWI #A          ; hex, zero padded
+        MOV R1 #6
+        SWI #B          ; binary
+        MOV R4 #8
+        SWI #C          ; octal
+        MOV R4 #1
+        SWI #C
+        HALT
+",
+    );
+    vm.set_console(Box::new(console.clone()));
+
+    let error = loop {
+        if let Err(error) = vm.execute_next(false) {
+            break error;
+        }
+    };
+
+    assert_eq!(console.output_string(), "  -1FFFF01100006");
+    assert_eq!(error.code(), -15);
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use common::machine;
+use rsmisc::instruction::Opcode;
+use rsmisc::json::Json;
+use rsmisc::outcome::Outcome;
+use rsmisc::swi::{SwiAction, SwiContext};
+use rsmisc::trace::{JsonLinesTracer, MemoryWrite, StackChange, TraceRecord};
+use rsmisc::{Rsmisc, RsmiscError};
+
+const PROGRAM: &str = "\
+        LD #1234
+        ULD 100
+        CALL #sub
+        NOP
+        HALT
+sub:    SWI #20
+        RET
+";
+
+// Shares what the machine writes with the test
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// A machine that collects its trace records
+fn traced(source: &str) -> (Rsmisc, Arc<Mutex<Vec<TraceRecord>>>) {
+    let records = Arc::new(Mutex::new(Vec::new()));
+    let mut vm = machine(source);
+    let sink = records.clone();
+
+    vm.register_swi(0x20, |_: &mut SwiContext| Ok(SwiAction::Return(0x7)));
+    vm.add_tracer(move |record: &TraceRecord| {
+        sink.lock().unwrap().push(record.clone());
+        Ok(())
+    });
+
+    (vm, records)
+}
+
+#[test]
+fn records_every_effect_of_an_instruction() {
+    let (mut vm, records) = traced(PROGRAM);
+    vm.run(100);
+
+    let records = records.lock().unwrap();
+    let ips: Vec<u16> = records.iter().map(|record| record.ip).collect();
+    assert_eq!(ips, vec![0x0, 0x6, 0xc, 0x1e, 0x24, 0x18]);
+    assert_eq!(records.last().unwrap().count, 6);
+
+    let load = &records[0];
+    assert_eq!(load.instruction.op_code, Opcode::LD);
+    assert_eq!(load.registers_before, load.registers_after);
+    assert_eq!(
+        load.stack,
+        StackChange {
+            pushed: Some(0x1234),
+            popped: None
+        }
+    );
+
+    let store = &records[1];
+    assert_eq!(store.stack.popped, Some(0x1234));
+    assert_eq!(
+        store.memory_writes,
+        vec![
+            MemoryWrite {
+                address: 0x100,
+                old: 0x0,
+                new: 0x12
+            },
+            MemoryWrite {
+                address: 0x101,
+                old: 0x0,
+                new: 0x34
+            }
+        ]
+    );
+
+    let call = &records[2];
+    assert_eq!(call.call_stack.pushed, Some(0x12));
+    assert_eq!(call.ip_after, 0x1e);
+
+    let interrupt = &records[3];
+    assert_eq!(interrupt.swi, Some(0x20));
+    assert_eq!(interrupt.registers_after[0], 0x7);
+
+    assert_eq!(records[4].call_stack.popped, Some(0x12));
+    assert_eq!(records[5].instruction.op_code, Opcode::HALT);
+}
+
+#[test]
+fn faulting_instructions_are_recorded() {
+    let (mut vm, records) = traced("MOV R1 #7\nDIV R1 #0\nHALT");
+    let outcome = vm.run(100).outcome;
+
+    let records = records.lock().unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(matches!(
+        records[1].error,
+        Some(RsmiscError::DivisionByZero { .. })
+    ));
+    assert_eq!(outcome, Outcome::Fault(records[1].error.clone().unwrap()));
+}
+
+#[test]
+fn faults_before_execution_are_not_recorded() {
+    let (mut vm, records) = traced("NOP\n.byte D 0 0 0 0 0\nHALT");
+
+    vm.set_gas(Some(0x0));
+    assert!(matches!(
+        vm.execute_next(false),
+        Err(RsmiscError::OutOfGas { .. })
+    ));
+
+    vm.set_gas(None);
+    vm.set_strict_decoding(true);
+    vm.execute_next(false).unwrap();
+    assert!(matches!(
+        vm.execute_next(false),
+        Err(RsmiscError::IllegalInstruction { .. })
+    ));
+
+    let records = records.lock().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].instruction.op_code, Opcode::NOP);
+    assert_eq!(vm.instruction_count(), 1);
+}
+
+#[test]
+fn json_lines_hold_one_record_per_line() {
+    let buffer = Buffer::default();
+    let mut vm = machine(PROGRAM);
+
+    vm.add_tracer(JsonLinesTracer::new(buffer.clone()));
+    vm.run(3);
+
+    let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<Json> = text
+        .lines()
+        .map(|line| Json::parse(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+
+    let store = &lines[1];
+    assert_eq!(store.get("opcode").and_then(Json::as_str), Some("ULD"));
+    assert_eq!(store.get("target").and_then(Json::as_str), Some("MA"));
+    assert_eq!(store.get("target_imm").and_then(Json::as_u64), Some(0x100));
+    assert_eq!(
+        store
+            .get("stack")
+            .and_then(|stack| stack.get("popped"))
+            .and_then(Json::as_u64),
+        Some(0x1234)
+    );
+    assert_eq!(
+        store
+            .get("memory_writes")
+            .and_then(Json::as_array)
+            .map(|writes| writes.len()),
+        Some(2)
+    );
+    assert_eq!(store.get("error"), Some(&Json::Null));
+}
+
+// io::Error::other needs Rust 1.74
+#[allow(clippy::io_other_error)]
+#[test]
+fn failing_sinks_stop_the_machine() {
+    let mut vm = machine("NOP\nHALT");
+    vm.add_tracer(|_: &TraceRecord| Err(io::Error::new(io::ErrorKind::Other, "disk full")));
+
+    assert!(matches!(
+        vm.execute_next(false),
+        Err(RsmiscError::Io { .. })
+    ));
+}
+
+#[test]
+fn tracing_does_not_enable_history() {
+    let (mut vm, _) = traced(PROGRAM);
+    vm.run(100);
+
+    assert_eq!(vm.history().count(), 0);
+    assert!(!vm.step_back());
+}
+
+#[test]
+fn traced_machines_move_between_threads() {
+    let (mut vm, records) = traced(PROGRAM);
+
+    std::thread::spawn(move || vm.run(100)).join().unwrap();
+
+    assert_eq!(records.lock().unwrap().len(), 6);
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use common::machine;
+use rsmisc::operand::Operand;
+use rsmisc::outcome::{Outcome, RunResult};
+use rsmisc::watch::{Access, WatchHit, Watchpoint};
+use rsmisc::RsmiscError;
+
+#[test]
+fn write_watchpoint_stops_after_the_store() {
+    let mut vm = machine("LD #5\nULD 101\nNOP\nHALT");
+
+    vm.add_watchpoint(Watchpoint::new(0x100, 0x101, Access::Write));
+
+    assert_eq!(
+        vm.run(100),
+        RunResult {
+            outcome: Outcome::Watchpoint {
+                ip: 0x6,
+                hits: vec![WatchHit::Memory {
+                    address: 0x101,
+                    access: Access::Write
+                }]
+            },
+            steps: 2
+        }
+    );
+    assert_eq!(vm.ip(), 0xc);
+    assert_eq!(vm.load_16(0x101).unwrap(), 0x5);
+    assert_eq!(vm.run(100).outcome, Outcome::Halted);
+}
+
+#[test]
+fn read_watchpoint_covers_operands_in_memory() {
+    // The read by ADD hits, the write by ULD does not
+    let mut vm = machine("LD #1\nULD 200\nADD R1 200\nHALT");
+
+    vm.add_watchpoint(Watchpoint::new(0x201, 0x201, Access::Read));
+
+    assert_eq!(
+        vm.run(100).outcome,
+        Outcome::Watchpoint {
+            ip: 0xc,
+            hits: vec![WatchHit::Memory {
+                address: 0x200,
+                access: Access::Read
+            }]
+        }
+    );
+}
+
+#[test]
+fn register_watchpoint_reports_changes_only() {
+    let mut vm = machine("LD #0\nULD R2\nLD #7\nULD R2\nHALT");
+
+    assert_eq!(vm.watch_register(Operand::R2), Ok(true));
+    assert_eq!(vm.watch_register(Operand::R2), Ok(false));
+
+    assert_eq!(
+        vm.run(100),
+        RunResult {
+            outcome: Outcome::Watchpoint {
+                ip: 0x12,
+                hits: vec![WatchHit::Register {
+                    register: Operand::R2,
+                    old: 0x0,
+                    new: 0x7
+                }]
+            },
+            steps: 4
+        }
+    );
+}
+
+#[test]
+fn ip_watchpoint_reports_jumps() {
+    let mut vm = machine("NOP\nCALL #sub\nHALT\nsub: RET");
+
+    vm.watch_register(Operand::IP).unwrap();
+
+    assert_eq!(
+        vm.run(100).outcome,
+        Outcome::Watchpoint {
+            ip: 0x6,
+            hits: vec![WatchHit::Register {
+                register: Operand::IP,
+                old: 0xc,
+                new: 0x12
+            }]
+        }
+    );
+}
+
+#[test]
+fn only_registers_can_be_watched() {
+    let mut vm = machine("HALT");
+
+    assert_eq!(
+        vm.watch_register(Operand::MA),
+        Err(RsmiscError::NotARegister {
+            operand: Operand::MA
+        })
+    );
+}
+
+#[test]
+fn removed_watchpoints_no_longer_stop() {
+    let mut vm = machine("LD #5\nULD 100\nHALT");
+    let watchpoint = Watchpoint::new(0x100, 0x1ff, Access::ReadWrite);
+
+    assert!(vm.add_watchpoint(watchpoint));
+    assert!(!vm.add_watchpoint(watchpoint));
+    assert!(vm.remove_watchpoint(watchpoint));
+    assert_eq!(vm.run(100).outcome, Outcome::Halted);
+}
+
+#[test]
+fn host_loads_and_stores_record_no_hits() {
+    let mut vm = machine("HALT");
+
+    vm.add_watchpoint(Watchpoint::new(0x100, 0x101, Access::ReadWrite));
+    vm.store_16(0x100, 0x5).unwrap();
+    assert_eq!(vm.load_16(0x100).unwrap(), 0x5);
+    assert!(vm.take_watch_hits().is_empty());
+
+    assert_eq!(vm.run(100).outcome, Outcome::Halted);
+    assert_eq!(vm.load_16(0x100).unwrap(), 0x5);
+    assert!(vm.take_watch_hits().is_empty());
+}
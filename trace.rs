@@ -0,0 +1,150 @@
+use std::io::{self, Write};
+
+use crate::instruction::Instruction;
+use crate::json::Json;
+use crate::RsmiscError;
+
+// One executed instruction, including one that faulted while running. Faults raised before it
+// runs (fetch, strict decoding and gas) leave the machine untouched and are not recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    // Value of `Rsmisc::instruction_count` after the instruction, starting at 1
+    pub count: u64,
+    pub ip: u16,
+    pub tword: u64,
+    pub instruction: Instruction,
+    // R1, R2, R3, R4
+    pub registers_before: [u16; 0x4],
+    pub registers_after: [u16; 0x4],
+    pub ip_after: u16,
+    pub stack: StackChange,
+    pub call_stack: StackChange,
+    // Every byte written, in order
+    pub memory_writes: Vec<MemoryWrite>,
+    // Number of the software interrupt a SWI invoked
+    pub swi: Option<u16>,
+    pub error: Option<RsmiscError>,
+}
+
+// An instruction pushes or pops at most one value on each stack
+#[derive(Copy, Debug, Clone, PartialEq, Eq, Default)]
+pub struct StackChange {
+    pub pushed: Option<u16>,
+    pub popped: Option<u16>,
+}
+
+impl StackChange {
+    // From the length and top of the stack before the instruction
+    pub(crate) fn between(length: usize, top: Option<u16>, stack: &[u16]) -> Self {
+        StackChange {
+            pushed: if stack.len() > length {
+                stack.last().copied()
+            } else {
+                None
+            },
+            popped: if stack.len() < length { top } else { None },
+        }
+    }
+}
+
+// `new` is the value the byte has after the instruction
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub address: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+impl TraceRecord {
+    pub fn to_json(&self) -> Json {
+        let words = |values: &[u16]| {
+            Json::from(
+                values
+                    .iter()
+                    .map(|value| Json::from(*value))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let stack = |change: &StackChange| {
+            Json::object(vec![
+                ("pushed", Json::from(change.pushed)),
+                ("popped", Json::from(change.popped)),
+            ])
+        };
+        let writes = self
+            .memory_writes
+            .iter()
+            .map(|write| {
+                Json::object(vec![
+                    ("address", Json::from(write.address)),
+                    ("old", Json::from(write.old as u16)),
+                    ("new", Json::from(write.new as u16)),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        Json::object(vec![
+            ("count", Json::from(self.count)),
+            ("ip", Json::from(self.ip)),
+            ("tword", Json::from(self.tword)),
+            ("opcode", Json::from(self.instruction.op_code.mnemonic())),
+            (
+                "target",
+                Json::from(format!("{:?}", self.instruction.target)),
+            ),
+            ("target_imm", Json::from(self.instruction.target_imm)),
+            (
+                "source",
+                Json::from(format!("{:?}", self.instruction.source)),
+            ),
+            ("source_imm", Json::from(self.instruction.source_imm)),
+            ("text", Json::from(self.instruction.to_string())),
+            ("registers_before", words(&self.registers_before)),
+            ("registers_after", words(&self.registers_after)),
+            ("ip_after", Json::from(self.ip_after)),
+            ("stack", stack(&self.stack)),
+            ("call_stack", stack(&self.call_stack)),
+            ("memory_writes", Json::from(writes)),
+            ("swi", Json::from(self.swi)),
+            (
+                "error",
+                Json::from(self.error.as_ref().map(|error| error.to_string())),
+            ),
+        ])
+    }
+}
+
+// Receives a record for every instruction `Rsmisc::execute_next` runs. An error stops the
+// machine with IO_ERROR, like a failing console write
+//
+// Tracers that collect results for the host, like `ChromeTrace` and `Profile`, are handles: the
+// machine owns one clone while the host reads the results from another
+pub trait Tracer {
+    fn trace(&mut self, record: &TraceRecord) -> io::Result<()>;
+}
+
+impl<F> Tracer for F
+where
+    F: FnMut(&TraceRecord) -> io::Result<()>,
+{
+    fn trace(&mut self, record: &TraceRecord) -> io::Result<()> {
+        self(record)
+    }
+}
+
+// Writes each record as one line of JSON
+pub struct JsonLinesTracer<W> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesTracer<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesTracer { writer }
+    }
+}
+
+impl<W: Write> Tracer for JsonLinesTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) -> io::Result<()> {
+        writeln!(self.writer, "{}", record.to_json())
+    }
+}
@@ -0,0 +1,51 @@
+use crate::operand::Operand;
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Access {
+    Read,
+    Write,
+    // Only used by watchpoints, a hit is always a read or a write
+    ReadWrite,
+}
+
+impl Access {
+    // Whether a watchpoint for `self` reports `access`
+    pub fn includes(self, access: Access) -> bool {
+        self == Access::ReadWrite || self == access
+    }
+}
+
+// Watches the bytes from `start` to `end` (inclusive) for data accesses by LD, ULD, the
+// arithmetic instructions and the built-in interrupts. Instruction fetches and the memory of
+// host interrupt handlers are not watched
+#[derive(Copy, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub access: Access,
+}
+
+impl Watchpoint {
+    pub fn new(start: u16, end: u16, access: Access) -> Self {
+        Self { start, end, access }
+    }
+
+    pub fn covers(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+}
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum WatchHit {
+    // `address` is the first byte of the access, which may start before the watched range
+    Memory {
+        address: u16,
+        access: Access,
+    },
+    // For IP, `old` is the address of the instruction after the one that moved it
+    Register {
+        register: Operand,
+        old: u16,
+        new: u16,
+    },
+}